@@ -7,8 +7,12 @@ use log::error;
 async fn main() {
     let _logger = init_logging(None);
 
-    if let Err(error) = cli::encoder::run().await {
-        error!("{error}");
-        exit(1);
+    match cli::encoder::run().await {
+        Ok(0) => {}
+        Ok(code) => exit(code),
+        Err(error) => {
+            error!("{error}");
+            exit(1);
+        }
     }
 }
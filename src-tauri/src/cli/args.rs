@@ -1,16 +1,64 @@
-use clap::Parser;
+use std::{
+    collections::HashMap,
+    io::IsTerminal,
+    path::{Path, PathBuf},
+};
+
+use clap::{Parser, Subcommand};
 use inquire::{Confirm, MultiSelect, Select};
 
-use crate::{Config, ProcessError, collect_presets};
+use crate::{
+    Config, ProcessError,
+    cli::{
+        backup::BackupCommand,
+        input::{SortBy, expand_inputs},
+        media::{OutputFormat, SubtitleCommand},
+        presets::PresetCommand,
+    },
+    collect_presets,
+    utils::{download::is_url, http_download::Checksum, presets::project_preset_path},
+};
 
 #[derive(Parser, Debug, Clone)]
 #[clap(version,
     about = "adConverter CLI",
-    long_about = None)]
+    long_about = None,
+    after_help = "Exit codes: 0 success, 1 error, 2 some files failed, 3 all files failed")]
 pub struct Args {
-    #[clap(short, long, help = "Files to encode", num_args = 1..)]
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+
+    #[clap(short, long, help = "Files, folders or URLs to encode", num_args = 1..)]
     pub files: Vec<String>,
 
+    #[clap(long, help = "Glob of files in folders to encode, like '*.mov'", num_args = 1..)]
+    pub include: Vec<String>,
+
+    #[clap(long, help = "Glob of files or folders to skip", num_args = 1..)]
+    pub exclude: Vec<String>,
+
+    #[clap(
+        long,
+        value_enum,
+        help = "Sort the input files, default is the given order"
+    )]
+    pub sort: Option<SortBy>,
+
+    #[clap(
+        long,
+        help = "Download all items of playlist URLs, not only the video of a URL"
+    )]
+    pub playlist: bool,
+
+    #[clap(long, help = "Items of playlist URLs, like '1-5,8'")]
+    pub playlist_items: Option<String>,
+
+    #[clap(
+        long,
+        help = "Expected hash of a direct download URL, like 'sha256:<hex>'"
+    )]
+    pub checksum: Option<String>,
+
     #[clap(short, long, help = "Language to transcript")]
     pub lang: Option<String>,
 
@@ -22,28 +70,226 @@ pub struct Args {
 
     #[clap(short, long, help = "Encoding presets", num_args = 0..)]
     pub presets: Option<Vec<String>>,
+
+    #[clap(long, help = "Encode all presets in one ffmpeg process")]
+    pub shared_decode: bool,
+
+    #[clap(
+        long,
+        global = true,
+        env = "ADCONVERTER_CONFIG",
+        help = "Config file, default is the config of the app"
+    )]
+    pub config: Option<PathBuf>,
+
+    #[clap(
+        long,
+        global = true,
+        env = "ADCONVERTER_PRESETS_DIR",
+        help = "User preset folder"
+    )]
+    pub presets_dir: Option<PathBuf>,
+
+    #[clap(
+        long,
+        global = true,
+        env = "ADCONVERTER_PROFILE",
+        help = "Config profile, default is the active profile"
+    )]
+    pub profile: Option<String>,
+
+    #[clap(short, long, help = "Target folder, default is the source folder")]
+    pub target: Option<String>,
+
+    #[clap(
+        long,
+        requires = "target",
+        help = "Create a subfolder per source in the target"
+    )]
+    pub target_subfolder: bool,
+
+    #[clap(
+        long,
+        help = "Template file or name of a stored template, default is a JSON file next to the source"
+    )]
+    pub template: Option<String>,
+
+    #[clap(long = "in", value_parser = parse_timecode, help = "Start, HH:MM:SS.mmm or seconds")]
+    pub start: Option<f64>,
+
+    #[clap(long = "out", value_parser = parse_timecode, help = "End, HH:MM:SS.mmm or seconds")]
+    pub end: Option<f64>,
+
+    #[clap(long, conflicts_with = "lang", help = "Skip the transcript")]
+    pub no_transcript: bool,
+
+    #[clap(
+        short,
+        long,
+        visible_alias = "non-interactive",
+        help = "Never prompt, use defaults or fail"
+    )]
+    pub yes: bool,
+}
+
+/// Parse `SS`, `MM:SS` or `HH:MM:SS`, seconds with an optional fraction.
+fn parse_timecode(value: &str) -> Result<f64, String> {
+    let error = || format!("invalid timecode \"{value}\", use HH:MM:SS.mmm or seconds");
+    let parts = value.trim().split(':').collect::<Vec<_>>();
+
+    if parts.len() > 3 {
+        return Err(error());
+    }
+
+    let mut seconds = 0.0;
+
+    for part in parts {
+        let number = part.parse::<f64>().map_err(|_| error())?;
+
+        if !number.is_finite() || number < 0.0 {
+            return Err(error());
+        }
+
+        seconds = seconds * 60.0 + number;
+    }
+
+    Ok(seconds)
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    #[clap(about = "Manage encoding presets")]
+    Presets {
+        #[clap(subcommand)]
+        action: PresetCommand,
+    },
+
+    #[clap(about = "Backup and restore settings, presets, templates and scripts")]
+    Backup {
+        #[clap(subcommand)]
+        action: BackupCommand,
+    },
+
+    #[clap(about = "Print media information")]
+    Probe {
+        #[clap(num_args = 1.., required = true)]
+        files: Vec<String>,
+        #[clap(long, value_enum, default_value_t)]
+        format: OutputFormat,
+    },
+
+    #[clap(about = "Measure loudness, true peak and loudness range")]
+    Loudness {
+        #[clap(num_args = 1.., required = true)]
+        files: Vec<String>,
+        #[clap(long, value_enum, default_value_t)]
+        format: OutputFormat,
+    },
+
+    #[clap(about = "Create only the transcript")]
+    Transcript {
+        #[clap(num_args = 1.., required = true)]
+        files: Vec<String>,
+        #[clap(short, long, default_value = "auto", help = "Language to transcript")]
+        lang: String,
+        #[clap(short, long, help = "Target folder, default is the source folder")]
+        target: Option<String>,
+    },
+
+    #[clap(about = "WebVTT subtitle tools")]
+    Subtitle {
+        #[clap(subcommand)]
+        action: SubtitleCommand,
+    },
+}
+
+/// Relative paths are resolved against the current folder, URLs are kept.
+pub fn absolute_path(file: String) -> String {
+    let path = Path::new(&file);
+
+    if path.is_relative() && !is_url(&file) {
+        std::env::current_dir()
+            .unwrap_or_default()
+            .join(path)
+            .to_string_lossy()
+            .to_string()
+    } else {
+        file
+    }
+}
+
+/// Preset names of each input, project presets next to a file add to the other layers.
+/// URLs only have the presets without project.
+async fn preset_names(
+    config: &Config,
+    files: &[String],
+) -> Result<Vec<(String, Vec<String>)>, ProcessError> {
+    let mut by_folder: HashMap<Option<PathBuf>, Vec<String>> = HashMap::new();
+    let mut names = vec![];
+
+    for file in files {
+        let media = (!is_url(file)).then(|| Path::new(file));
+        let folder = media.and_then(project_preset_path);
+
+        if !by_folder.contains_key(&folder) {
+            let list = collect_presets(&None, config, media)
+                .await?
+                .into_iter()
+                .map(|p| p.name)
+                .collect();
+
+            by_folder.insert(folder.clone(), list);
+        }
+
+        names.push((file.clone(), by_folder[&folder].clone()));
+    }
+
+    Ok(names)
 }
 
 impl Args {
-    pub async fn init(config: &Config) -> Result<Self, ProcessError> {
-        let mut obj = Self::parse();
-
-        obj.files = obj
-            .files
-            .into_iter()
-            .map(|file| {
-                let path = std::path::Path::new(&file);
-                if path.is_relative() {
-                    std::env::current_dir()
-                        .unwrap_or_default()
-                        .join(path)
-                        .to_string_lossy()
-                        .to_string()
-                } else {
-                    file
-                }
-            })
-            .collect();
+    pub async fn init(self, config: &Config) -> Result<Self, ProcessError> {
+        let mut obj = self;
+
+        obj.files = obj.files.into_iter().map(absolute_path).collect();
+
+        if obj.command.is_some() {
+            return Ok(obj);
+        }
+
+        if obj.files.is_empty() {
+            return Err(ProcessError::Custom(
+                "Add files to encode: -f <[FILES]>".to_string(),
+            ));
+        }
+
+        obj.files = expand_inputs(obj.files, &obj.include, &obj.exclude, obj.sort).await?;
+
+        if let Some(checksum) = &obj.checksum {
+            if obj.files.iter().filter(|f| is_url(f)).count() != 1 {
+                return Err(ProcessError::Custom(
+                    "--checksum works only with one URL".to_string(),
+                ));
+            }
+
+            Checksum::parse(checksum)?;
+        }
+
+        if obj.files.is_empty() {
+            return Err(ProcessError::Custom(
+                "No media files found in the given inputs".to_string(),
+            ));
+        }
+
+        if let (Some(start), Some(end)) = (obj.start, obj.end)
+            && end <= start
+        {
+            return Err(ProcessError::Custom(format!(
+                "--out ({end}s) must be after --in ({start}s)"
+            )));
+        }
+
+        let interactive = !obj.yes && std::io::stdin().is_terminal();
 
         let lang_list: Vec<String> = config
             .transcript_lang
@@ -51,41 +297,104 @@ impl Args {
             .map(|r| r.name.clone())
             .collect();
 
-        let mut preset_list: Vec<String> = collect_presets(&None)
-            .await?
-            .iter()
-            .map(|r| r.name.clone())
-            .collect();
+        let file_presets = preset_names(config, &obj.files).await?;
+        let mut preset_list: Vec<String> = vec![];
 
-        preset_list.insert(0, "None".to_string());
-
-        if obj.lang.is_none() {
-            let lang = Select::new("Transcript Language:", lang_list).prompt()?;
+        for name in file_presets.iter().flat_map(|(_, names)| names) {
+            if !preset_list.contains(name) {
+                preset_list.push(name.clone());
+            }
+        }
 
-            obj.lang = Some(lang);
+        if obj.no_transcript {
+            obj.lang = Some("none".to_string());
         }
 
-        if obj.fade.is_none() {
-            let fade = Confirm::new("Apply fade [Y/n]:").prompt()?;
+        if interactive {
+            if obj.lang.is_none() {
+                let lang = Select::new("Transcript Language:", lang_list).prompt()?;
+
+                obj.lang = Some(lang);
+            }
+
+            if obj.fade.is_none() {
+                let fade = Confirm::new("Apply fade [Y/n]:").prompt()?;
+
+                obj.fade = Some(fade);
+            }
+
+            if obj.lufs.is_none() {
+                let lufs = Confirm::new("Apply loudnorm [Y/n]:").prompt()?;
+
+                obj.lufs = Some(lufs);
+            }
+
+            if obj.presets.is_none() {
+                let mut choices = preset_list.clone();
+                choices.insert(0, "None".to_string());
 
-            obj.fade = Some(fade);
+                let defaults = choices
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, name)| config.default_presets.contains(name))
+                    .map(|(i, _)| i)
+                    .collect::<Vec<_>>();
+                let presets = MultiSelect::new("Encoding presets:", choices)
+                    .with_default(&defaults)
+                    .prompt()?;
+
+                let presets = presets
+                    .into_iter()
+                    .filter(|p| p != "None")
+                    .collect::<Vec<String>>();
+
+                obj.presets = Some(presets);
+            }
+        } else {
+            obj.lang.get_or_insert_with(|| "none".to_string());
+            obj.fade.get_or_insert(false);
+            obj.lufs.get_or_insert(false);
+            obj.presets
+                .get_or_insert_with(|| config.default_presets.clone());
         }
 
-        if obj.lufs.is_none() {
-            let lufs = Confirm::new("Apply loudnorm [Y/n]:").prompt()?;
+        let presets = obj.presets.as_deref().unwrap_or_default();
+        let unknown = presets
+            .iter()
+            .filter(|p| !preset_list.contains(p))
+            .cloned()
+            .collect::<Vec<_>>();
 
-            obj.lufs = Some(lufs);
+        if !unknown.is_empty() {
+            return Err(ProcessError::Custom(format!(
+                "Unknown preset: {}, available: {}",
+                unknown.join(", "),
+                preset_list.join(", ")
+            )));
         }
 
-        if obj.presets.is_none() {
-            let presets = MultiSelect::new("Encoding presets:", preset_list).prompt()?;
+        // Project presets only exist for the files in their folder.
+        let missing = file_presets
+            .iter()
+            .flat_map(|(file, names)| {
+                presets
+                    .iter()
+                    .filter(|p| !names.contains(p))
+                    .map(move |p| format!("{p} for {file}"))
+            })
+            .collect::<Vec<_>>();
 
-            let presets = presets
-                .into_iter()
-                .filter(|p| p != "None")
-                .collect::<Vec<String>>();
+        if !missing.is_empty() {
+            return Err(ProcessError::Custom(format!(
+                "Preset not available: {}",
+                missing.join(", ")
+            )));
+        }
 
-            obj.presets = Some(presets);
+        if !interactive && presets.is_empty() && obj.lang.as_deref() == Some("none") {
+            return Err(ProcessError::Custom(
+                "Nothing to do, add --presets or --lang".to_string(),
+            ));
         }
 
         obj.lang = obj.lang.map(|lang| config.code_from(&lang));
@@ -93,3 +402,18 @@ impl Args {
         Ok(obj)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_timecodes() {
+        assert_eq!(parse_timecode("90"), Ok(90.0));
+        assert_eq!(parse_timecode("1:30.5"), Ok(90.5));
+        assert_eq!(parse_timecode("01:00:01.250"), Ok(3601.25));
+        assert!(parse_timecode("1:2:3:4").is_err());
+        assert!(parse_timecode("-5").is_err());
+        assert!(parse_timecode("ab").is_err());
+    }
+}
@@ -0,0 +1,122 @@
+use std::path::{Path, PathBuf};
+
+use clap::Subcommand;
+use serde_json::{Map, Value};
+use tokio::fs;
+
+use crate::{
+    ProcessError,
+    utils::{
+        backup::{Backup, BackupEntry, EntryStatus},
+        config::config_path,
+    },
+};
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum BackupCommand {
+    #[clap(about = "Export config, user presets, templates and scripts to a zip file")]
+    Export {
+        #[clap(help = "Target zip file")]
+        output: PathBuf,
+        #[clap(
+            long,
+            help = "Include passwords, keys and tokens of the publish targets"
+        )]
+        secrets: bool,
+    },
+
+    #[clap(about = "Import a backup, new entries are added, conflicts are kept by default")]
+    Import {
+        file: PathBuf,
+        #[clap(long, help = "Replace conflicting entries with the backup")]
+        overwrite: bool,
+        #[clap(long, help = "Only show what would be imported")]
+        dry_run: bool,
+    },
+}
+
+async fn read_config_map(path: &Path) -> Result<Map<String, Value>, ProcessError> {
+    if !path.is_file() {
+        return Ok(Map::new());
+    }
+
+    Ok(serde_json::from_slice(&fs::read(path).await?)?)
+}
+
+fn print_entries(entries: &[BackupEntry]) {
+    let width = entries.iter().map(|e| e.name.len()).max().unwrap_or(0);
+
+    for entry in entries {
+        println!(
+            "{:9} {:width$}  {:?}",
+            format!("{:?}", entry.kind).to_lowercase(),
+            entry.name,
+            entry.status
+        );
+    }
+}
+
+pub async fn run(command: BackupCommand, config: Option<PathBuf>) -> Result<(), ProcessError> {
+    let app = None;
+    let config_path = match config {
+        Some(path) => path,
+        None => config_path(&app)?,
+    };
+
+    match command {
+        BackupCommand::Export { output, secrets } => {
+            let config = read_config_map(&config_path).await?;
+
+            Backup::collect(&app, config, secrets)
+                .await?
+                .write(&output)
+                .await?;
+            println!("Saved backup to {output:?}");
+        }
+        BackupCommand::Import {
+            file,
+            overwrite,
+            dry_run,
+        } => {
+            let backup = Backup::read(&file).await?;
+            let mut config = read_config_map(&config_path).await?;
+            let preview = backup.preview(&app, &config).await?;
+
+            println!(
+                "Backup from adConverter {}, created {}\n",
+                preview.manifest.app_version, preview.manifest.created
+            );
+            print_entries(&preview.entries);
+
+            if dry_run {
+                return Ok(());
+            }
+
+            let mut restored = backup.merge_config(&mut config, overwrite);
+
+            if !restored.is_empty() {
+                if let Some(parent) = config_path.parent() {
+                    fs::create_dir_all(parent).await?;
+                }
+
+                fs::write(&config_path, serde_json::to_string_pretty(&config)?).await?;
+            }
+
+            restored.extend(backup.restore_files(&app, overwrite).await?);
+
+            let kept = preview
+                .entries
+                .iter()
+                .filter(|e| e.status == EntryStatus::Conflict)
+                .count();
+
+            println!("\nImported {} entries", restored.len());
+
+            if kept > 0 && !overwrite {
+                println!("Kept {kept} conflicting entries, use --overwrite to replace them");
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -1,32 +1,70 @@
-use std::{
-    process::exit,
-    sync::{Arc, atomic::AtomicBool},
-};
+use std::sync::{Arc, atomic::AtomicBool};
 
+use clap::Parser;
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use log::error;
 use tokio::sync::Mutex;
 
 use crate::{
     cli::{
-        args::Args,
+        args::{Args, Command, absolute_path},
+        backup, media, presets,
+        summary::{Outcome, Summary},
         utils::{create_tasks, read_config},
     },
-    utils::errors::ProcessError,
+    utils::{errors::ProcessError, presets::set_cli_preset_path},
     worker::work,
 };
 
-pub async fn run() -> Result<(), ProcessError> {
-    let config = read_config().await?;
-    let args = Args::init(&config).await?;
+/// Returns the exit code, see [`Summary::exit_code`].
+pub async fn run() -> Result<i32, ProcessError> {
+    let args = Args::parse();
+
+    if let Some(directory) = &args.presets_dir {
+        set_cli_preset_path(directory.clone());
+    }
+
+    // Restoring a backup works without a valid config file.
+    if let Some(Command::Backup { action }) = args.command.clone() {
+        return backup::run(action, args.config.clone()).await.map(|()| 0);
+    }
+
+    let config = read_config(args.config.as_deref(), args.profile.as_deref()).await?;
+    let args = args.init(&config).await?;
 
-    if args.files.is_empty() {
-        error!("Add files to encode: -f <[FILES]>");
-        exit(1);
+    match args.command.clone() {
+        Some(Command::Presets { action }) => {
+            return presets::run(action, &config).await.map(|()| 0);
+        }
+        Some(Command::Probe { files, format }) => {
+            let files = files.into_iter().map(absolute_path).collect::<Vec<_>>();
+
+            return media::probe(&config, &files, format).await.map(|()| 0);
+        }
+        Some(Command::Loudness { files, format }) => {
+            let files = files.into_iter().map(absolute_path).collect::<Vec<_>>();
+
+            return media::loudness(&config, &files, format).await.map(|()| 0);
+        }
+        Some(Command::Transcript {
+            files,
+            lang,
+            target,
+        }) => {
+            let files = files.into_iter().map(absolute_path).collect::<Vec<_>>();
+
+            return media::transcript(&config, &files, &lang, target)
+                .await
+                .map(|()| 0);
+        }
+        Some(Command::Subtitle { action }) => {
+            return media::subtitle(&config, action).await.map(|()| 0);
+        }
+        Some(Command::Backup { .. }) | None => {}
     }
 
-    // let preset_length = args.presets.as_ref().unwrap_or(&vec![]).len();
-    let tasks = create_tasks(&config, args).await;
+    let mut summary = Summary::default();
+    let tasks = create_tasks(&config, &args, &mut summary).await?;
     let task_length = tasks.len();
 
     let multi_prog = MultiProgress::new();
@@ -42,10 +80,8 @@ pub async fn run() -> Result<(), ProcessError> {
     let all = multi_prog.add(ProgressBar::new(n));
     all.set_style(sty.clone());
 
-    // let length = tasks.len() * preset_length;
-
     for (i, task) in tasks.iter().enumerate() {
-        work(
+        let result = work(
             None,
             config.clone(),
             Arc::new(Mutex::new(None)),
@@ -53,7 +89,18 @@ pub async fn run() -> Result<(), ProcessError> {
             task.clone(),
             Some(current.clone()),
         )
-        .await?;
+        .await;
+
+        match result {
+            Ok(uploads) => {
+                summary.push(&task.path, Outcome::Done);
+                summary.push_uploads(&task.path, &uploads);
+            }
+            Err(e) => {
+                error!("{}: {e}", task.path);
+                summary.push(&task.path, Outcome::Failed(e.to_string()));
+            }
+        }
 
         let prog = ((i + 1) * 100 / task_length) as u64;
 
@@ -67,5 +114,7 @@ pub async fn run() -> Result<(), ProcessError> {
     println!("\n");
     multi_prog.clear().unwrap();
 
-    Ok(())
+    summary.print();
+
+    Ok(summary.exit_code())
 }
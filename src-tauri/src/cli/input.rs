@@ -0,0 +1,233 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::UNIX_EPOCH,
+};
+
+use async_walkdir::{Filtering, WalkDir};
+use clap::ValueEnum;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use tokio::fs;
+use tokio_stream::StreamExt;
+
+use crate::{
+    ProcessError,
+    utils::{AUDIO_EXTENSIONS, VIDEO_EXTENSIONS, download::is_url},
+};
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SortBy {
+    Name,
+    /// Oldest first.
+    Modified,
+    /// Smallest first.
+    Size,
+}
+
+fn glob_set(patterns: &[String]) -> Result<Option<GlobSet>, ProcessError> {
+    if patterns.is_empty() {
+        return Ok(None);
+    }
+
+    let mut builder = GlobSetBuilder::new();
+
+    for pattern in patterns {
+        let glob = Glob::new(pattern)
+            .map_err(|e| ProcessError::Custom(format!("Invalid glob \"{pattern}\": {e}")))?;
+
+        builder.add(glob);
+    }
+
+    builder
+        .build()
+        .map(Some)
+        .map_err(|e| ProcessError::Custom(e.to_string()))
+}
+
+fn extension(path: &Path) -> String {
+    path.extension()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_lowercase()
+}
+
+fn is_video(path: &Path) -> bool {
+    VIDEO_EXTENSIONS.contains(&extension(path).as_str())
+}
+
+fn is_audio(path: &Path) -> bool {
+    AUDIO_EXTENSIONS.contains(&extension(path).as_str())
+}
+
+/// Match the path relative to the walked folder, or only its name.
+fn matches(set: &GlobSet, root: &Path, path: &Path) -> bool {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+
+    set.is_match(relative) || path.file_name().is_some_and(|name| set.is_match(name))
+}
+
+/// Walk a folder recursively for media files, hidden and excluded entries are skipped.
+async fn walk(
+    root: &Path,
+    include: Option<&GlobSet>,
+    exclude: Option<Arc<GlobSet>>,
+) -> Result<Vec<PathBuf>, ProcessError> {
+    let filter_root = root.to_path_buf();
+    let mut entries = WalkDir::new(root).filter(move |entry| {
+        let root = filter_root.clone();
+        let exclude = exclude.clone();
+
+        async move {
+            let path = entry.path();
+            let hidden = entry.file_name().to_string_lossy().starts_with('.');
+
+            if hidden || exclude.is_some_and(|set| matches(&set, &root, &path)) {
+                Filtering::IgnoreDir
+            } else {
+                Filtering::Continue
+            }
+        }
+    });
+    let mut files = vec![];
+
+    while let Some(entry) = entries.next().await {
+        let path = entry.map_err(|e| ProcessError::IO(e.to_string()))?.path();
+
+        if path.is_file()
+            && (is_video(&path) || is_audio(&path))
+            && include.is_none_or(|set| matches(set, root, &path))
+        {
+            files.push(path);
+        }
+    }
+
+    files.sort();
+
+    Ok(files)
+}
+
+/// Drop audio files with the same folder and name as a video, the video task uses them as audio source.
+fn drop_sidecars(files: Vec<PathBuf>) -> Vec<PathBuf> {
+    let videos = files
+        .iter()
+        .filter(|path| is_video(path))
+        .map(|path| path.with_extension(""))
+        .collect::<HashSet<_>>();
+
+    files
+        .into_iter()
+        .filter(|path| !is_audio(path) || !videos.contains(&path.with_extension("")))
+        .collect()
+}
+
+async fn sort_files(files: &mut Vec<PathBuf>, sort: SortBy) -> Result<(), ProcessError> {
+    if sort == SortBy::Name {
+        files.sort_by_key(|path| {
+            path.file_name()
+                .map(|name| name.to_string_lossy().to_lowercase())
+        });
+
+        return Ok(());
+    }
+
+    let mut keyed = vec![];
+
+    // Missing files are sorted first and reported later.
+    for file in files.drain(..) {
+        let meta = fs::metadata(&file).await.ok();
+        let key = match sort {
+            SortBy::Modified => meta
+                .and_then(|m| m.modified().ok())
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .unwrap_or_default()
+                .as_millis(),
+            _ => meta.map_or(0, |m| u128::from(m.len())),
+        };
+
+        keyed.push((key, file));
+    }
+
+    keyed.sort_by_key(|(key, _)| *key);
+    files.extend(keyed.into_iter().map(|(_, file)| file));
+
+    Ok(())
+}
+
+/// Expand folders to the media files inside, files are taken as they are.
+///
+/// Filters only apply to folder content, duplicates and audio sidecars of a video are removed.
+/// URLs are kept and added after the files.
+pub async fn expand_inputs(
+    inputs: Vec<String>,
+    include: &[String],
+    exclude: &[String],
+    sort: Option<SortBy>,
+) -> Result<Vec<String>, ProcessError> {
+    let include = glob_set(include)?;
+    let exclude = glob_set(exclude)?.map(Arc::new);
+    let mut files = vec![];
+    let mut urls = vec![];
+
+    for input in inputs {
+        if is_url(&input) {
+            if !urls.contains(&input) {
+                urls.push(input);
+            }
+
+            continue;
+        }
+
+        let path = PathBuf::from(input);
+
+        if path.is_dir() {
+            files.extend(walk(&path, include.as_ref(), exclude.clone()).await?);
+        } else {
+            files.push(path);
+        }
+    }
+
+    let mut seen = HashSet::new();
+    files.retain(|file| seen.insert(file.clone()));
+
+    let mut files = drop_sidecars(files);
+
+    if let Some(sort) = sort {
+        sort_files(&mut files, sort).await?;
+    }
+
+    Ok(files
+        .into_iter()
+        .map(|file| file.to_string_lossy().to_string())
+        .chain(urls)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drops_audio_sidecars() {
+        let files = ["/a/clip.mov", "/a/clip.wav", "/a/music.wav", "/b/clip.wav"]
+            .map(PathBuf::from)
+            .to_vec();
+
+        assert_eq!(
+            drop_sidecars(files),
+            ["/a/clip.mov", "/a/music.wav", "/b/clip.wav"].map(PathBuf::from)
+        );
+    }
+
+    #[test]
+    fn globs_match_relative_paths_and_names() {
+        let set = glob_set(&["00-audio".to_string(), "raw/*.mxf".to_string()])
+            .unwrap()
+            .unwrap();
+        let root = Path::new("/media");
+
+        assert!(matches(&set, root, Path::new("/media/show/00-audio")));
+        assert!(matches(&set, root, Path::new("/media/raw/take.mxf")));
+        assert!(!matches(&set, root, Path::new("/media/final/take.mxf")));
+    }
+}
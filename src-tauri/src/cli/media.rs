@@ -0,0 +1,289 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, atomic::AtomicBool},
+};
+
+use clap::{Subcommand, ValueEnum};
+use serde_json::Value;
+use tokio::sync::Mutex;
+
+use crate::{
+    Config, ProcessError, Task,
+    cli::utils::progress_bar,
+    ffmpeg::{analyze::Lufs, probe::MediaProbe},
+    transcript::{self, process::optimize_vtt},
+    utils::{Sources, logging::CommandLogger},
+};
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default)]
+pub enum OutputFormat {
+    #[default]
+    Table,
+    Json,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum SubtitleCommand {
+    #[clap(about = "Fix overlaps, split long cues and trim WebVTT files to the video length")]
+    Optimize {
+        #[clap(num_args = 1.., required = true)]
+        files: Vec<PathBuf>,
+        #[clap(
+            short,
+            long,
+            default_value = "auto",
+            help = "Language of the subtitles"
+        )]
+        lang: String,
+        #[clap(
+            long,
+            help = "Media file for the length, default is a video with the same name"
+        )]
+        media: Option<PathBuf>,
+        #[clap(short, long, help = "Target file, default overwrites the source")]
+        output: Option<PathBuf>,
+    },
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or(path.as_os_str())
+        .to_string_lossy()
+        .to_string()
+}
+
+fn size(bytes: i64) -> String {
+    let mut value = bytes as f64;
+    let mut unit = "B";
+
+    for next in ["KB", "MB", "GB", "TB"] {
+        if value < 1000.0 {
+            break;
+        }
+
+        value /= 1000.0;
+        unit = next;
+    }
+
+    format!("{value:.1} {unit}")
+}
+
+fn print_probe(path: &Path, probe: &MediaProbe) {
+    let format = &probe.format;
+
+    println!("{}", file_name(path));
+    println!(
+        "  format   {:.3}s, {} streams, {}",
+        format.duration.unwrap_or_default(),
+        format.nb_streams,
+        size(format.size)
+    );
+
+    for (i, video) in probe.video.iter().enumerate() {
+        println!(
+            "  video {i}  {} {}x{} {}, {} frames, {} kb/s",
+            video.codec_name.as_deref().unwrap_or("-"),
+            video.width.unwrap_or_default(),
+            video.height.unwrap_or_default(),
+            video.aspect_ratio.as_deref().unwrap_or("-"),
+            video.nb_frames.unwrap_or_default(),
+            video.bit_rate.unwrap_or_default() / 1000
+        );
+    }
+
+    for (i, audio) in probe.audio.iter().enumerate() {
+        println!(
+            "  audio {i}  {} {} Hz, {} channels",
+            audio.codec_name.as_deref().unwrap_or("-"),
+            audio.sample_rate.unwrap_or_default(),
+            audio.channels
+        );
+    }
+}
+
+/// Print media information of each file.
+pub async fn probe(
+    config: &Config,
+    files: &[String],
+    format: OutputFormat,
+) -> Result<(), ProcessError> {
+    let mut list = vec![];
+
+    for file in files {
+        let path = Path::new(file);
+        let probe = MediaProbe::new(config, path).await?;
+
+        match format {
+            OutputFormat::Table => print_probe(path, &probe),
+            OutputFormat::Json => {
+                let mut value = serde_json::to_value(&probe)?;
+                value["path"] = Value::from(file.as_str());
+
+                list.push(value);
+            }
+        }
+    }
+
+    if let OutputFormat::Json = format {
+        println!("{}", serde_json::to_string_pretty(&list)?);
+    }
+
+    Ok(())
+}
+
+/// Measure the integrated loudness, range and true peak of each file.
+pub async fn loudness(
+    config: &Config,
+    files: &[String],
+    format: OutputFormat,
+) -> Result<(), ProcessError> {
+    let mut list = vec![];
+
+    for file in files {
+        let path = Path::new(file);
+        let duration = MediaProbe::new(config, path).await?.format_duration();
+        let bar = progress_bar("Analyze");
+
+        let lufs = Lufs::new(
+            None,
+            config,
+            duration,
+            Arc::new(AtomicBool::new(true)),
+            Arc::new(Mutex::new(None)),
+            vec!["-i".to_string(), file.clone()],
+            CommandLogger::new(),
+            Some(bar.clone()),
+        )
+        .await?;
+
+        bar.finish_and_clear();
+        list.push((file, lufs));
+    }
+
+    match format {
+        OutputFormat::Table => {
+            let width = list
+                .iter()
+                .map(|(file, _)| file_name(Path::new(file)).len())
+                .max()
+                .unwrap_or(0);
+
+            println!(
+                "{:width$}  {:>7}  {:>5}  {:>5}  {:>9}",
+                "File", "LUFS", "LRA", "TP", "Threshold"
+            );
+
+            for (file, lufs) in &list {
+                println!(
+                    "{:width$}  {:>7.2}  {:>5.2}  {:>5.2}  {:>9.2}",
+                    file_name(Path::new(file)),
+                    lufs.input_i,
+                    lufs.input_lra,
+                    lufs.input_tp,
+                    lufs.input_thresh
+                );
+            }
+        }
+        OutputFormat::Json => {
+            let mut values = vec![];
+
+            for (file, lufs) in list {
+                let mut value = serde_json::to_value(&lufs)?;
+                value["path"] = Value::from(file.as_str());
+
+                values.push(value);
+            }
+
+            println!("{}", serde_json::to_string_pretty(&values)?);
+        }
+    }
+
+    Ok(())
+}
+
+/// Run only the transcript command, the WebVTT file is saved next to the source or in `target`.
+pub async fn transcript(
+    config: &Config,
+    files: &[String],
+    lang: &str,
+    target: Option<String>,
+) -> Result<(), ProcessError> {
+    let lang = config.code_from(lang);
+
+    for file in files {
+        let sources = Sources::new(file).await?;
+        let source = sources
+            .audio
+            .or(sources.video)
+            .map_or_else(|| PathBuf::from(file), PathBuf::from);
+        let task = Task {
+            path: file.clone(),
+            transcript: Some(lang.clone()),
+            probe: MediaProbe::new(config, file).await?,
+            target: target.clone(),
+            ..Default::default()
+        };
+        let bar = progress_bar("Dictate");
+
+        transcript::run(
+            None,
+            config.clone(),
+            Arc::new(Mutex::new(None)),
+            Arc::new(AtomicBool::new(true)),
+            CommandLogger::new(),
+            &source,
+            &task,
+            Some(bar.clone()),
+        )
+        .await?;
+
+        bar.finish_and_clear();
+        println!("Transcribed {}", file_name(Path::new(file)));
+    }
+
+    Ok(())
+}
+
+/// Video with the same name as the subtitle file.
+async fn subtitle_media(vtt: &Path) -> Option<PathBuf> {
+    let vtt = std::path::absolute(vtt).ok()?;
+    let sources = Sources::new(&vtt.to_string_lossy()).await.ok()?;
+
+    sources.video.map(PathBuf::from)
+}
+
+pub async fn subtitle(config: &Config, command: SubtitleCommand) -> Result<(), ProcessError> {
+    match command {
+        SubtitleCommand::Optimize {
+            files,
+            lang,
+            media,
+            output,
+        } => {
+            if output.is_some() && files.len() > 1 {
+                return Err(ProcessError::Custom(
+                    "--output works only with one file".to_string(),
+                ));
+            }
+
+            let lang = config.code_from(&lang);
+
+            for file in files {
+                let media = match &media {
+                    Some(m) => Some(m.clone()),
+                    None => subtitle_media(&file).await,
+                };
+                let duration = match media {
+                    Some(m) => MediaProbe::new(config, m).await?.format_duration(),
+                    None => 0.0,
+                };
+                let target = output.clone().unwrap_or_else(|| file.clone());
+
+                optimize_vtt(&file, &target, (duration * 1000.0) as u64, &lang).await?;
+                println!("Optimized {target:?}");
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -1,5 +1,10 @@
 pub mod args;
+pub mod backup;
 pub mod encoder;
+pub mod input;
+pub mod media;
+pub mod presets;
+pub mod summary;
 pub mod utils;
 
 pub const IDENTIFIER: &str = "com.adconverter";
@@ -0,0 +1,111 @@
+use std::path::PathBuf;
+
+use clap::Subcommand;
+
+use crate::{
+    Config, ProcessError, collect_presets,
+    utils::presets::{
+        delete_preset, duplicate_preset, export_presets, import_presets, preset_dirs, rename_preset,
+    },
+};
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum PresetCommand {
+    #[clap(about = "List all presets")]
+    List,
+
+    #[clap(about = "Print a preset as JSON, with resolved inheritance")]
+    Show { name: String },
+
+    #[clap(about = "Delete a preset")]
+    Delete { name: String },
+
+    #[clap(about = "Rename a preset")]
+    Rename { name: String, new_name: String },
+
+    #[clap(about = "Copy a preset")]
+    Duplicate {
+        name: String,
+        #[clap(help = "Name of the copy, default: <NAME>_2")]
+        new_name: Option<String>,
+    },
+
+    #[clap(about = "Import presets from a JSON or zip file")]
+    Import {
+        file: PathBuf,
+        #[clap(
+            long,
+            help = "Replace existing presets with the same name, also shadow bundled and shared ones"
+        )]
+        overwrite: bool,
+    },
+
+    #[clap(about = "Export presets to a JSON or zip file")]
+    Export {
+        #[clap(short, long, help = "Target file")]
+        output: PathBuf,
+        #[clap(num_args = 1.., required = true)]
+        names: Vec<String>,
+    },
+}
+
+pub async fn run(command: PresetCommand, config: &Config) -> Result<(), ProcessError> {
+    let app = None;
+
+    match command {
+        PresetCommand::List => {
+            let presets = collect_presets(&app, config, None).await?;
+            let width = presets.iter().map(|p| p.name.len()).max().unwrap_or(0);
+
+            println!("Preset folders, lowest precedence first:");
+
+            for (source, path) in preset_dirs(&app, config, None)? {
+                println!("  {source:8}  {path:?}");
+            }
+
+            println!();
+
+            for preset in presets {
+                let source = preset
+                    .source
+                    .as_ref()
+                    .map(ToString::to_string)
+                    .unwrap_or_default();
+
+                println!("{:width$}  {source:8}  {}", preset.name, preset.title);
+            }
+        }
+        PresetCommand::Show { name } => {
+            let preset = collect_presets(&app, config, None)
+                .await?
+                .into_iter()
+                .find(|p| p.name == name)
+                .ok_or_else(|| ProcessError::Custom(format!("Preset \"{name}\" not found")))?;
+
+            println!("{}", serde_json::to_string_pretty(&preset)?);
+        }
+        PresetCommand::Delete { name } => {
+            delete_preset(&app, &name).await?;
+            println!("Deleted preset \"{name}\"");
+        }
+        PresetCommand::Rename { name, new_name } => {
+            rename_preset(&app, config, &name, &new_name).await?;
+            println!("Renamed preset \"{name}\" to \"{new_name}\"");
+        }
+        PresetCommand::Duplicate { name, new_name } => {
+            let new_name = duplicate_preset(&app, config, &name, new_name.as_deref()).await?;
+            println!("Copied preset \"{name}\" to \"{new_name}\"");
+        }
+        PresetCommand::Import { file, overwrite } => {
+            for name in import_presets(&app, config, &file, overwrite).await? {
+                println!("Imported preset \"{name}\"");
+            }
+        }
+        PresetCommand::Export { output, names } => {
+            export_presets(&app, config, &names, &output).await?;
+            println!("Exported {} preset(s) to {output:?}", names.len());
+        }
+    }
+
+    Ok(())
+}
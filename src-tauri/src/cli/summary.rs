@@ -0,0 +1,132 @@
+use std::path::Path;
+
+use crate::publisher::{PublishResult, PublishStatus};
+
+/// Some files failed, others were encoded.
+pub const EXIT_PARTIAL: i32 = 2;
+/// No file could be encoded.
+pub const EXIT_FAILED: i32 = 3;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Done,
+    Skipped(String),
+    Failed(String),
+}
+
+/// Result of each input file of a CLI run.
+#[derive(Clone, Debug, Default)]
+pub struct Summary {
+    entries: Vec<(String, Outcome)>,
+}
+
+impl Summary {
+    pub fn push(&mut self, file: impl Into<String>, outcome: Outcome) {
+        self.entries.push((file.into(), outcome));
+    }
+
+    /// Every upload of a file is an entry, a failed upload fails the run like a failed file.
+    pub fn push_uploads(&mut self, file: &str, results: &[PublishResult]) {
+        let name = Path::new(file)
+            .file_name()
+            .map_or(file.to_string(), |name| name.to_string_lossy().to_string());
+
+        for result in results {
+            let outcome = match result.status {
+                PublishStatus::Published => Outcome::Done,
+                PublishStatus::Failed => {
+                    Outcome::Failed(result.message.clone().unwrap_or_default())
+                }
+                PublishStatus::Cancelled => Outcome::Skipped("upload cancelled".to_string()),
+            };
+
+            self.push(
+                format!("{name} ({} -> {})", result.preset, result.target),
+                outcome,
+            );
+        }
+    }
+
+    fn count(&self, matches: fn(&Outcome) -> bool) -> usize {
+        self.entries.iter().filter(|(_, o)| matches(o)).count()
+    }
+
+    pub fn print(&self) {
+        let names = self
+            .entries
+            .iter()
+            .map(|(file, _)| {
+                Path::new(file)
+                    .file_name()
+                    .map_or(file.clone(), |name| name.to_string_lossy().to_string())
+            })
+            .collect::<Vec<_>>();
+        let width = names.iter().map(String::len).max().unwrap_or(0);
+
+        for (name, (_, outcome)) in names.iter().zip(&self.entries) {
+            match outcome {
+                Outcome::Done => println!("{name:width$}  done"),
+                Outcome::Skipped(reason) => println!("{name:width$}  skipped  {reason}"),
+                Outcome::Failed(reason) => println!("{name:width$}  failed   {reason}"),
+            }
+        }
+
+        println!(
+            "\n{} done, {} skipped, {} failed",
+            self.count(|o| *o == Outcome::Done),
+            self.count(|o| matches!(o, Outcome::Skipped(_))),
+            self.count(|o| matches!(o, Outcome::Failed(_)))
+        );
+    }
+
+    /// 0 without failures, [`EXIT_PARTIAL`] or [`EXIT_FAILED`] when nothing was done.
+    pub fn exit_code(&self) -> i32 {
+        let failed = self.count(|o| matches!(o, Outcome::Failed(_)));
+
+        if failed == 0 {
+            0
+        } else if self.count(|o| *o == Outcome::Done) > 0 {
+            EXIT_PARTIAL
+        } else {
+            EXIT_FAILED
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_codes() {
+        let mut summary = Summary::default();
+        summary.push("a.mov", Outcome::Done);
+        summary.push("b.mov", Outcome::Skipped("no preset applies".to_string()));
+        assert_eq!(summary.exit_code(), 0);
+
+        summary.push("c.mov", Outcome::Failed("probe failed".to_string()));
+        assert_eq!(summary.exit_code(), EXIT_PARTIAL);
+
+        summary.entries.remove(0);
+        assert_eq!(summary.exit_code(), EXIT_FAILED);
+    }
+
+    #[test]
+    fn failed_uploads_fail_the_run() {
+        let mut summary = Summary::default();
+        summary.push("a.mov", Outcome::Done);
+        summary.push_uploads(
+            "/media/a.mov",
+            &[PublishResult {
+                target: "Cloud".to_string(),
+                preset: "HD".to_string(),
+                status: PublishStatus::Failed,
+                url: None,
+                message: Some("401 Unauthorized".to_string()),
+            }],
+        );
+
+        assert_eq!(summary.entries[1].0, "a.mov (HD -> Cloud)");
+        assert_eq!(summary.exit_code(), EXIT_PARTIAL);
+    }
+}
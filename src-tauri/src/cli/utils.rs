@@ -1,77 +1,280 @@
-use std::sync::{Arc, atomic::AtomicBool};
+use std::{
+    env,
+    path::Path,
+    sync::{Arc, atomic::AtomicBool},
+};
 
-use dirs::data_dir;
-use serde_json;
-use tokio::{fs::File, io::AsyncReadExt};
+use indicatif::{ProgressBar, ProgressStyle};
+use log::*;
+use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
 
 use crate::{
     Config, Task,
-    cli::{IDENTIFIER, args::Args},
+    cli::{
+        args::Args,
+        summary::{Outcome, Summary},
+    },
     collect_presets,
     ffmpeg::probe::MediaProbe,
-    utils::errors::ProcessError,
+    utils::{
+        Sources,
+        config::config_path,
+        download::{self, is_url},
+        errors::ProcessError,
+        http_download,
+        metadata::SourceMetadata,
+        template::Template,
+    },
 };
 
-pub async fn read_config() -> Result<Config, ProcessError> {
-    let config_path = data_dir()
-        .expect("Data dir")
-        .join(IDENTIFIER)
-        .join("config.json");
+/// Read the config file, the built-in defaults are used when the default file doesn't exist.
+///
+/// The profile and `ADCONVERTER_*` environment variables are applied on top, before validation.
+/// Only the default config file is saved after a migration, a given file can be read-only.
+pub async fn read_config(
+    path: Option<&Path>,
+    profile: Option<&str>,
+) -> Result<Config, ProcessError> {
+    let config = match path {
+        Some(path) => {
+            let (config, migrated) = Config::load(path).await?;
 
-    if !config_path.is_file() {
-        return Err(ProcessError::Custom("No config file found!".to_string()));
-    }
+            if migrated {
+                info!("Config {path:?} is migrated in memory, the file stays unchanged");
+            }
 
-    let mut file = File::open(config_path).await?;
-    let mut buffer = Vec::new();
-    file.read_to_end(&mut buffer).await?;
+            config
+        }
+        None => {
+            let path = config_path(&None)?;
 
-    let mut config: Config = serde_json::from_slice(&buffer)?;
+            if path.is_file() {
+                let (config, migrated) = Config::load(&path).await?;
 
-    if let Some(ffmpeg_path) = &config.ffmpeg_path {
-        if ffmpeg_path.is_file() {
-            config.ffmpeg_path = ffmpeg_path.parent().map(|path| path.to_path_buf());
-        } else if !ffmpeg_path.is_dir() {
-            config.ffmpeg_path = None;
+                if migrated {
+                    config.save(&path).await?;
+                }
+
+                config
+            } else {
+                info!("No config file found at {path:?}, use defaults");
+                Config::default()
+            }
         }
-    }
+    };
+
+    let mut config = match profile {
+        Some(name) => config.with_profile(name)?,
+        None => config.active(),
+    };
+
+    config.apply_env()?;
+    config.validate()?;
+    config.normalize();
 
     Ok(config)
 }
 
-pub async fn create_tasks(config: &Config, args: Args) -> Vec<Task> {
-    let preset_list = collect_presets(&None).await.unwrap_or_default();
-    let selected_presets = args.presets.unwrap_or_default();
-    let mut presets = vec![];
-    let mut tasks = vec![];
+pub fn progress_bar(prefix: &str) -> ProgressBar {
+    let bar = ProgressBar::new(100);
+    let style = ProgressStyle::with_template(
+        "{prefix}: [{elapsed_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} {msg}",
+    )
+    .unwrap()
+    .progress_chars("#-");
+
+    bar.set_style(style);
+    bar.set_prefix(prefix.to_string());
 
-    for preset in preset_list {
-        if selected_presets.contains(&preset.name) {
-            presets.push(preset);
+    bar
+}
+
+/// Download a URL into the download folder, or the current folder.
+///
+/// Direct media links are downloaded natively, other URLs with yt-dlp.
+/// Playlists return one file per new item.
+async fn download_url(
+    config: &Config,
+    url: &str,
+    args: &Args,
+) -> Result<Vec<String>, ProcessError> {
+    let directory = match &config.download_path {
+        Some(path) => path.clone(),
+        None => env::current_dir()?,
+    };
+    let bar = progress_bar("Download");
+    bar.set_message(url.to_string());
+
+    let progress = |progress: download::Progress| {
+        if progress.count > 1 {
+            bar.set_message(format!("item {}/{} {url}", progress.item, progress.count));
+        }
+
+        bar.set_position(progress.percent as u64);
+    };
+
+    let files = match http_download::download(
+        url,
+        &directory,
+        args.checksum.as_deref(),
+        CancellationToken::new(),
+        &progress,
+    )
+    .await
+    {
+        Ok(Some(file)) => Ok(vec![file]),
+        Ok(None) => {
+            download::download(
+                url.to_string(),
+                directory,
+                config,
+                args.playlist,
+                args.playlist_items.as_deref(),
+                Arc::new(Mutex::new(None)),
+                &progress,
+            )
+            .await
         }
+        Err(e) => Err(e),
+    };
+
+    bar.finish_and_clear();
+
+    files
+}
+
+async fn create_task(
+    config: &Config,
+    args: &Args,
+    index: usize,
+    file: &str,
+    template: Option<&Template>,
+) -> Result<Task, ProcessError> {
+    let selected_presets = args.presets.as_deref().unwrap_or_default();
+
+    // Project presets next to the file can replace the selected presets.
+    let presets: Vec<_> = collect_presets(&None, config, Some(Path::new(file)))
+        .await?
+        .into_iter()
+        .filter(|preset| selected_presets.contains(&preset.name))
+        .collect();
+    let missing = selected_presets
+        .iter()
+        .filter(|name| !presets.iter().any(|p| &&p.name == name))
+        .cloned()
+        .collect::<Vec<_>>();
+
+    // Like a downloaded file in another folder than the project presets.
+    if !missing.is_empty() {
+        return Err(ProcessError::Custom(format!(
+            "Preset not available: {}",
+            missing.join(", ")
+        )));
     }
 
-    for (index, file) in args.files.into_iter().enumerate() {
-        let task = Task {
-            id: format!("cli-{index}"),
-            path: file.clone(),
-            url: None,
-            r#in: 0.0,
-            out: 0.0,
-            fade: args.fade.unwrap_or_default(),
-            lufs: args.lufs.unwrap_or_default(),
-            transcript: args.lang.clone(),
-            probe: MediaProbe::new(config, file).await.unwrap_or_default(),
-            presets: presets.clone(),
-            template: None,
-            target: None,
-            target_subfolder: false,
-            publish: None,
-            active: Arc::new(AtomicBool::new(false)),
-            finished: Arc::new(AtomicBool::new(false)),
+    // Without --template use the template file next to the source, like the GUI.
+    let template = match template {
+        Some(t) => Some(t.clone()),
+        None => match Sources::new(file).await?.template {
+            Some(src) => Some(Template::new(&src).await?),
+            None => None,
+        },
+    };
+
+    let probe = MediaProbe::new(config, file)
+        .await
+        .map_err(|e| ProcessError::Ffprobe(format!("Probe failed: {e}")))?;
+
+    Ok(Task {
+        id: format!("cli-{index}"),
+        path: file.to_string(),
+        url: None,
+        playlist: false,
+        playlist_items: None,
+        checksum: None,
+        r#in: args.start.unwrap_or_default(),
+        out: args.end.unwrap_or_default(),
+        fade: args.fade.unwrap_or_default(),
+        lufs: args.lufs.unwrap_or_default(),
+        transcript: args.lang.clone(),
+        probe,
+        presets,
+        template,
+        target: args.target.clone(),
+        target_subfolder: args.target_subfolder,
+        publish: None,
+        publish_results: vec![],
+        metadata: SourceMetadata::read(file).await,
+        shared_decode: args.shared_decode,
+        profile: config.profile.clone(),
+        active: Arc::new(AtomicBool::new(false)),
+        finished: Arc::new(AtomicBool::new(false)),
+    })
+}
+
+/// Tasks of all files which can be encoded, the others are added to the summary.
+pub async fn create_tasks(
+    config: &Config,
+    args: &Args,
+    summary: &mut Summary,
+) -> Result<Vec<Task>, ProcessError> {
+    let mut tasks = vec![];
+
+    // A file path or the name of a stored template.
+    let template = match &args.template {
+        Some(path) if Path::new(path).is_file() => Some(Template::new(Path::new(path)).await?),
+        Some(name) => Some(Template::named(&None, name).await?),
+        None => None,
+    };
+
+    let mut index = 0;
+
+    for input in &args.files {
+        let files = if is_url(input) {
+            match download_url(config, input, args).await {
+                Ok(files) if files.is_empty() => {
+                    summary.push(
+                        input,
+                        Outcome::Skipped("already in the download archive".to_string()),
+                    );
+                    continue;
+                }
+                Ok(files) => files,
+                Err(e) => {
+                    summary.push(input, Outcome::Failed(e.to_string()));
+                    continue;
+                }
+            }
+        } else if Path::new(input).is_file() {
+            vec![input.clone()]
+        } else {
+            summary.push(input, Outcome::Failed("file not found".to_string()));
+            continue;
         };
-        tasks.push(task);
+
+        for file in files {
+            let task = create_task(config, args, index, &file, template.as_ref())
+                .await
+                .map(|task| Task {
+                    url: is_url(input).then(|| input.clone()),
+                    ..task
+                });
+
+            index += 1;
+
+            match task {
+                Ok(task)
+                    if task.presets.is_empty()
+                        && task.transcript.as_ref().is_none_or(|t| t == "none") =>
+                {
+                    summary.push(file, Outcome::Skipped("no preset selected".to_string()));
+                }
+                Ok(task) => tasks.push(task),
+                Err(e) => summary.push(file, Outcome::Failed(e.to_string())),
+            }
+        }
     }
 
-    tasks
+    Ok(tasks)
 }
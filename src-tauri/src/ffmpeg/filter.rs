@@ -11,7 +11,11 @@ use tokio::fs;
 use super::{analyze::Lufs, prepare_path, probe::MediaProbe};
 use crate::{
     Config, Preset, Task, Template,
-    utils::{IMAGE_EXTENSIONS, find_audio, is_close, time_to_sec},
+    utils::{
+        IMAGE_EXTENSIONS, find_audio, is_close,
+        presets::{Entry, arg_entries, entry_value, filter_entries},
+        time_to_sec,
+    },
     vec_strings,
 };
 
@@ -45,6 +49,8 @@ pub struct Filters {
     video_position: i32,
     audio_last: i32,
     video_last: i32,
+    /// Prefix of the link labels, see `with_prefix`.
+    prefix: String,
 }
 
 impl Filters {
@@ -61,6 +67,7 @@ impl Filters {
             video_position: -1,
             audio_last: -1,
             video_last: -1,
+            prefix: String::new(),
         }
     }
 
@@ -125,11 +132,11 @@ impl Filters {
         let mut a_chain = self.audio_chain.clone();
 
         if self.video_last >= 0 && !v_chain.ends_with(']') {
-            v_chain.push_str(&format!("[vout{}]", self.video_last));
+            v_chain.push_str(&format!("[{}vout{}]", self.prefix, self.video_last));
         }
 
         if self.audio_last >= 0 && !a_chain.ends_with(']') {
-            a_chain.push_str(&format!("[aout{}]", self.audio_last));
+            a_chain.push_str(&format!("[{}aout{}]", self.prefix, self.audio_last));
         }
 
         let mut f_chain = v_chain;
@@ -155,6 +162,23 @@ impl Filters {
         cmd
     }
 
+    /// Copy of the filters with all link labels prefixed, so that the graphs
+    /// of several presets can live in one `-filter_complex`.
+    pub fn with_prefix(&self, prefix: &str) -> Self {
+        let re = Regex::new(r"\[([a-z_][a-z0-9_]*)\]").unwrap();
+        let rename = |s: &str| re.replace_all(s, format!("[{prefix}$1]")).to_string();
+
+        Self {
+            audio_chain: rename(&self.audio_chain),
+            video_chain: rename(&self.video_chain),
+            output_chain: self.output_chain.iter().map(|c| rename(c)).collect(),
+            audio_map: self.audio_map.iter().map(|m| rename(m)).collect(),
+            video_map: self.video_map.iter().map(|m| rename(m)).collect(),
+            prefix: format!("{prefix}{}", self.prefix),
+            ..self.clone()
+        }
+    }
+
     pub fn map_video(&mut self) -> Vec<String> {
         let v_map = "0:v".to_string();
         let mut o_map = vec_strings!["-map"];
@@ -201,51 +225,41 @@ impl TargetSpec {
         let mut aspect = 0.0;
         let mut resolution = (-1, -1);
 
-        fn resolution_from_map(
-            map: &serde_json::Map<String, Value>,
-            key: &str,
-        ) -> Option<(i64, i64)> {
-            if let Some(Value::String(s)) = map.get(key) {
-                let (w, h) = s
-                    .split_once(':')
-                    .or_else(|| s.split_once('x'))
-                    .unwrap_or(("-1", "-1"));
-                let w = w.parse::<i64>().unwrap_or_default();
-                let h = h.parse::<i64>().unwrap_or_default();
-                if w > 0 && h > 0 {
-                    return Some((w, h));
-                }
-            }
-            None
+        fn resolution_from(entries: &[Entry], key: &str) -> Option<(i64, i64)> {
+            let s = entry_value(entries, key)?;
+            let (w, h) = s
+                .split_once(':')
+                .or_else(|| s.split_once('x'))
+                .unwrap_or(("-1", "-1"));
+            let w = w.parse::<i64>().unwrap_or_default();
+            let h = h.parse::<i64>().unwrap_or_default();
+
+            (w > 0 && h > 0).then_some((w, h))
         }
 
-        if let Value::Object(map) = &preset.filter_video {
-            if let Some(res) = resolution_from_map(map, "scale") {
-                resolution = res;
-            }
+        let filter_video = filter_entries(&preset.filter_video);
+        let video = arg_entries(&preset.video);
 
-            if let Some(Value::String(aspect_str)) = map.get("setdar")
-                && let Some(aspect_value) = aspect_str
-                    .split_once('=')
-                    .map(|s| s.1)
-                    .and_then(|n| n.parse::<f64>().ok())
-            {
-                aspect = aspect_value;
-            }
+        if let Some(res) = resolution_from(&filter_video, "scale") {
+            resolution = res;
         }
 
-        if let Value::Object(map) = &preset.video {
-            if let Some(res) = resolution_from_map(map, "-s") {
-                resolution = res;
-            }
+        if let Some(aspect_value) = entry_value(&filter_video, "setdar")
+            .and_then(|s| s.split_once('='))
+            .and_then(|(_, n)| n.parse::<f64>().ok())
+        {
+            aspect = aspect_value;
+        }
 
-            if let Some(Value::String(aspect_str)) = map.get("-aspect")
-                && let Some((w, h)) = aspect_str
-                    .split_once(':')
-                    .and_then(|(n1, n2)| Some((n1.parse::<f64>().ok()?, n2.parse::<f64>().ok()?)))
-            {
-                aspect = w / h;
-            }
+        if let Some(res) = resolution_from(&video, "-s") {
+            resolution = res;
+        }
+
+        if let Some((w, h)) = entry_value(&video, "-aspect")
+            .and_then(|s| s.split_once(':'))
+            .and_then(|(n1, n2)| Some((n1.parse::<f64>().ok()?, n2.parse::<f64>().ok()?)))
+        {
+            aspect = w / h;
         }
 
         if (resolution.0 <= 0 || resolution.1 <= 0)
@@ -287,6 +301,68 @@ fn move_concat(input: &str) -> String {
     }
 }
 
+/// Decode input streams which are used by more than one filter graph only once
+/// and fan them out with split/asplit.
+pub fn split_inputs(graph: &str) -> String {
+    let re = Regex::new(r"\[(\d+):([av]):(\d+)\]").unwrap();
+    let mut counts: Vec<(String, usize)> = vec![];
+
+    for caps in re.captures_iter(graph) {
+        let spec = caps[0].to_string();
+
+        match counts.iter_mut().find(|(s, _)| s == &spec) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((spec, 1)),
+        }
+    }
+
+    let mut used: Vec<(String, usize)> = vec![];
+    let mut splits = vec![];
+
+    let graph = re
+        .replace_all(graph, |caps: &regex::Captures| {
+            let spec = &caps[0];
+            let total = counts
+                .iter()
+                .find(|(s, _)| s == spec)
+                .map_or(1, |(_, c)| *c);
+
+            if total < 2 {
+                return spec.to_string();
+            }
+
+            let index = match used.iter_mut().find(|(s, _)| s == spec) {
+                Some((_, i)) => {
+                    *i += 1;
+                    *i
+                }
+                None => {
+                    used.push((spec.to_string(), 0));
+                    0
+                }
+            };
+
+            format!("[s{}{}{}_{index}]", &caps[1], &caps[2], &caps[3])
+        })
+        .to_string();
+
+    for (spec, total) in counts.iter().filter(|(_, c)| *c > 1) {
+        let caps = re.captures(spec).unwrap();
+        let typ = if &caps[2] == "a" { "asplit" } else { "split" };
+        let outputs = (0..*total)
+            .map(|i| format!("[s{}{}{}_{i}]", &caps[1], &caps[2], &caps[3]))
+            .collect::<String>();
+
+        splits.push(format!("{spec}{typ}={total}{outputs}"));
+    }
+
+    if splits.is_empty() {
+        return graph;
+    }
+
+    format!("{};{graph}", splits.join(";"))
+}
+
 fn pad(probe: &MediaProbe, target_spec: &TargetSpec) -> String {
     let v_stream = probe.video.first();
     let source_aspect = probe.aspect();
@@ -411,10 +487,8 @@ async fn lower_third(
             ));
         }
 
-        if let Some(scale) = preset.filter_video.get("scale") {
-            if let Value::String(s) = scale {
-                layer_base.push_str(&format!(",scale={s},setdar=dar={:.3}", target_spec.aspect));
-            }
+        if let Some(s) = entry_value(&filter_entries(&preset.filter_video), "scale") {
+            layer_base.push_str(&format!(",scale={s},setdar=dar={:.3}", target_spec.aspect));
         } else if let Some(w) = task_probe.video[0].width
             && let Some(h) = task_probe.video[0].height
         {
@@ -543,10 +617,8 @@ async fn intro_outro(
 
     let mut sf = "null".to_string();
 
-    if let Some(scale) = preset.filter_video.get("scale") {
-        if let Value::String(s) = scale
-            && let Some(prob) = intro_probe.or(outro_probe)
-        {
+    if let Some(s) = entry_value(&filter_entries(&preset.filter_video), "scale") {
+        if let Some(prob) = intro_probe.or(outro_probe) {
             let mut pad_f = pad(&prob, target_spec);
 
             if !pad_f.is_empty() {
@@ -604,29 +676,26 @@ async fn intro_outro(
 fn map_filter(value: &Value, target_spec: &TargetSpec) -> String {
     let mut filters = vec![];
 
-    if let Value::Object(map) = value {
-        for (key, val) in map {
-            let v = match val {
-                Value::String(s) => s.clone(),
-                _ => val.to_string(),
-            };
-
-            let mut filter = format!("{key}={v}");
+    for (key, values) in filter_entries(value) {
+        let mut filter = if values.is_empty() {
+            key.clone()
+        } else {
+            format!("{key}={}", values.join(":"))
+        };
 
-            if key == "scale" && target_spec.aspect > 0.0 {
-                filter.push_str(&format!(",setdar=dar={:.3}", target_spec.aspect));
+        if key == "scale" && target_spec.aspect > 0.0 {
+            filter.push_str(&format!(",setdar=dar={:.3}", target_spec.aspect));
 
-                if is_close(
-                    target_spec.width as f64 / target_spec.height as f64,
-                    target_spec.aspect,
-                    0.3,
-                ) {
-                    filter.push_str(",setsar=sar=1/1");
-                }
+            if is_close(
+                target_spec.width as f64 / target_spec.height as f64,
+                target_spec.aspect,
+                0.3,
+            ) {
+                filter.push_str(",setsar=sar=1/1");
             }
-
-            filters.push(filter);
         }
+
+        filters.push(filter);
     }
 
     filters.join(",")
@@ -643,17 +712,10 @@ fn has_codec_copy(args: &Value, typ: FilterType) -> bool {
     let alias_3 = format!("-c:{}", s);
     let alias_4 = "-c";
 
-    if let Value::Object(map) = args {
-        for (key, value) in map {
-            if (key == &alias_1 || key == &alias_2 || key == &alias_3 || key == alias_4)
-                && value == "copy"
-            {
-                return true;
-            }
-        }
-    }
-
-    false
+    arg_entries(args).iter().any(|(key, values)| {
+        (key == &alias_1 || key == &alias_2 || key == &alias_3 || key == alias_4)
+            && values.first().is_some_and(|v| v == "copy")
+    })
 }
 
 fn loudnorm(lufs: &Lufs) -> String {
@@ -821,6 +883,7 @@ mod tests {
     fn preset() -> Preset {
         Preset {
             name: "adtv".to_string(),
+            extends: None,
             title: "ADtv".to_string(),
             tooltip: "Encoding settings for ADTV (x265)".to_string(),
             input: None,
@@ -857,6 +920,9 @@ mod tests {
             },
             container_video: Some("mp4".to_string()),
             container_audio: None,
+            rules: vec![],
+            publish_targets: vec![],
+            source: None,
             finished: Arc::new(AtomicBool::new(false)),
             output_path: None,
         }
@@ -903,6 +969,8 @@ mod tests {
             id: "test-task".to_string(),
             path: path.to_string_lossy().to_string(),
             url: None,
+            playlist: false,
+            playlist_items: None,
             presets: vec![preset()],
             template: Some(template()),
             fade: true,
@@ -913,11 +981,16 @@ mod tests {
                 .await
                 .unwrap(),
             publish: None,
+            publish_results: vec![],
+            checksum: None,
+            metadata: None,
             active: Arc::new(AtomicBool::new(false)),
             finished: Arc::new(AtomicBool::new(false)),
             lufs: false,
             target: None,
             target_subfolder: false,
+            shared_decode: false,
+            profile: None,
         }
     }
 
@@ -961,4 +1034,29 @@ mod tests {
 
         println!("{res_asp:?}");
     }
+
+    #[test]
+    fn ordered_list_form() {
+        let filter = serde_json::json!([["scale", "1280:720"], "hflip", "fps=25"]);
+        let video = serde_json::json!([["-c:v", "copy"], "-map 0:v"]);
+        let target_spec = TargetSpec::default();
+
+        assert_eq!(
+            map_filter(&filter, &target_spec),
+            "scale=1280:720,hflip,fps=25"
+        );
+        assert!(has_codec_copy(&video, Video));
+        assert!(!has_codec_copy(&video, Audio));
+    }
+
+    #[test]
+    fn split_shared_inputs() {
+        let graph =
+            "[0:v:0]scale=1280:720[p0_vout0];[0:v:0]scale=640:360[p1_vout0];[1:a:0]anull[p0_aout0]";
+
+        assert_eq!(
+            split_inputs(graph),
+            "[0:v:0]split=2[s0v0_0][s0v0_1];[s0v0_0]scale=1280:720[p0_vout0];[s0v0_1]scale=640:360[p1_vout0];[1:a:0]anull[p0_aout0]"
+        );
+    }
 }
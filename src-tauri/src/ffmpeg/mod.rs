@@ -21,6 +21,14 @@ pub struct FFmpegProgress {
     pub progress: String,
 }
 
+/// Encoding of a task failed, the worker continues with the next task.
+#[derive(Clone, Debug, Serialize, TS)]
+#[ts(export, export_to = "backend.d.ts")]
+pub struct TaskError {
+    pub id: String,
+    pub message: String,
+}
+
 impl FFmpegProgress {
     fn new(map: &HashMap<String, String>, duration: f64) -> Self {
         let seconds = map
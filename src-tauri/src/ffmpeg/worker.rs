@@ -22,13 +22,21 @@ use tokio::{
     sync::{Mutex, mpsc::Receiver},
 };
 
-use super::{FFmpegProgress, analyze::Lufs, filter::filter_chain, probe::MediaProbe};
-use crate::{AppState, ProcessError, Task, transcript, vec_strings};
+use super::{
+    FFmpegProgress, TaskError,
+    analyze::Lufs,
+    filter::{Filters, filter_chain, split_inputs},
+    probe::MediaProbe,
+};
+use crate::{
+    AppState, Preset, ProcessError, PublishResult, Task, publisher, transcript, vec_strings,
+};
 use crate::{
     Config,
     utils::{
         Sources,
         logging::{CommandLogger, log_command},
+        presets::arg_entries,
     },
 };
 
@@ -43,27 +51,154 @@ const IGNORE_LINES: &[&str] = &[
 
 fn is_empty(value: &Value) -> bool {
     match value {
+        Value::Null => true,
         Value::Object(map) => map.is_empty(),
         Value::Array(arr) => arr.is_empty(),
         _ => false,
     }
 }
 
-fn to_vec(value: Value) -> Vec<String> {
+fn to_vec(value: &Value) -> Vec<String> {
     let mut params = Vec::new();
 
-    if let Value::Object(map) = value {
-        for (key, val) in map {
-            params.push(key);
+    for (key, values) in arg_entries(value) {
+        params.push(key);
+        params.extend(values);
+    }
+
+    params
+}
+
+/// One preset of a task, prepared for encoding.
+struct Job {
+    preset: Preset,
+    output: PathBuf,
+    temp_out: PathBuf,
+    filter: Filters,
+    has_video: bool,
+}
+
+impl Job {
+    fn output_args(&mut self, has_audio: bool) -> Vec<String> {
+        let mut args = vec![];
+
+        if let Some(video_ext) = &self.preset.container_video {
+            if self.has_video {
+                args.extend(self.filter.map_video());
+                args.extend(to_vec(&self.preset.video));
+            }
+
+            if video_ext.eq_ignore_ascii_case("mp4") {
+                args.extend(vec_strings!["-movflags", "+faststart"]);
+            }
+        }
+
+        if let Some(audio_ext) = &self.preset.container_audio {
+            if self.has_video {
+                args.push(self.temp_out.to_string_lossy().to_string());
+            }
+
+            if has_audio {
+                args.extend(self.filter.map_audio());
+                args.extend(to_vec(&self.preset.audio));
+            }
+
+            args.push(
+                self.temp_out
+                    .with_extension(audio_ext)
+                    .to_string_lossy()
+                    .to_string(),
+            );
+        } else {
+            if has_audio {
+                args.extend(self.filter.map_audio());
+                args.extend(to_vec(&self.preset.audio));
+            }
 
-            match val {
-                Value::String(s) => params.push(s),
-                _ => params.push(val.to_string()),
+            if self.has_video {
+                args.push(self.temp_out.to_string_lossy().to_string());
             }
         }
+
+        args
     }
+}
 
-    params
+/// Presets with their own input arguments (hardware decoding, etc.)
+/// can't share the decoder with other presets.
+fn is_shareable(preset: &Preset) -> bool {
+    preset.input.as_ref().is_none_or(|i| i.trim().is_empty())
+}
+
+/// One filter graph for a group of jobs, which share the decoded input.
+///
+/// The labels of each job get their own prefix, also in the output maps of the jobs.
+fn shared_graph(group: &mut [Job]) -> Option<String> {
+    let mut graphs = vec![];
+
+    for (i, job) in group.iter_mut().enumerate() {
+        job.filter = job.filter.with_prefix(&format!("p{i}_"));
+
+        if let Some(graph) = job.filter.cmd().get(1) {
+            graphs.push(graph.clone());
+        }
+    }
+
+    (!graphs.is_empty()).then(|| split_inputs(&graphs.join(";")))
+}
+
+/// Input of a source, cut to the in and out points of the task.
+///
+/// The cut belongs to the input, so it applies to every output of a shared decode
+/// and an intro or outro is not cut off.
+fn trimmed_input(task: &Task, path: &str) -> Vec<String> {
+    let mut args = vec![];
+
+    if task.r#in > 0.0 {
+        args.extend(vec_strings!["-ss", task.r#in]);
+    }
+
+    if task.out > 0.0 {
+        args.extend(vec_strings!["-t", task.out - task.r#in]);
+    }
+
+    args.extend(vec_strings!["-i", path]);
+
+    args
+}
+
+/// Arguments of one ffmpeg process, which encodes all jobs of the group.
+fn group_args(
+    task_args: &[String],
+    group: &mut [Job],
+    metadata: &[String],
+    has_audio: bool,
+) -> Vec<String> {
+    let mut args = task_args.to_vec();
+
+    if group.len() > 1 {
+        if let Some(graph) = shared_graph(group) {
+            args.extend(vec_strings!["-filter_complex", graph]);
+        }
+    } else if let Some(job) = group.first_mut() {
+        if let Some(input_args) = &job.preset.input {
+            let i_args = split(input_args).unwrap_or_default();
+
+            if let Some(pos) = args.iter().position(|x| x == "-i") {
+                // Insert before input "-i"
+                args.splice(pos..pos, i_args);
+            }
+        }
+
+        args.extend(job.filter.cmd());
+    }
+
+    for job in group {
+        args.extend(metadata.to_vec());
+        args.extend(job.output_args(has_audio));
+    }
+
+    args
 }
 
 async fn calc_duration(config: &Config, task: &Task) -> (f64, f64, f64) {
@@ -96,6 +231,133 @@ async fn calc_duration(config: &Config, task: &Task) -> (f64, f64, f64) {
     (duration_intro, duration, duration_outro)
 }
 
+/// Run one ffmpeg process and report its progress for every preset title it encodes.
+#[allow(clippy::too_many_arguments)]
+async fn encode(
+    app: Option<AppHandle>,
+    ff_bin: &Path,
+    args: Vec<String>,
+    titles: Vec<String>,
+    duration: f64,
+    child: Arc<Mutex<Option<Child>>>,
+    is_running: Arc<AtomicBool>,
+    mut cmd_logger: CommandLogger,
+    progress_bar: Option<ProgressBar>,
+) -> Result<(), ProcessError> {
+    let app_some = app.is_some();
+    let running = is_running.clone();
+    let running_clone = is_running.clone();
+    let title = titles.join(", ");
+
+    let mut cmd = Command::new(ff_bin);
+
+    cmd.args(args).stderr(Stdio::piped()).stdout(Stdio::piped());
+
+    #[cfg(target_os = "macos")]
+    cmd.env("PATH", MACOS_PATH);
+
+    #[cfg(target_os = "windows")]
+    cmd.creation_flags(0x08000000);
+
+    let mut proc = cmd.spawn()?;
+
+    let stderr = proc.stderr.take().expect("Failed to capture stderr");
+    let stdout = proc.stdout.take().expect("Failed to capture stdout");
+
+    *child.lock().await = Some(proc);
+
+    let stderr_task = tokio::spawn(async move {
+        let mut reader = BufReader::new(stderr).lines();
+
+        while let Some(line) = reader.next_line().await.expect("Read line") {
+            if !running.load(Ordering::SeqCst) {
+                break;
+            }
+
+            if !IGNORE_LINES.iter().any(|&s| line.contains(s))
+                && !line.is_empty()
+                && !line.starts_with("encoded")
+                && (app_some || !line.contains("[info]"))
+            {
+                cmd_logger.log(Some("[ffmpeg]"), &line);
+            }
+        }
+    });
+
+    let mut stat_map = HashMap::new();
+    stat_map.insert("title".to_string(), title.clone());
+
+    if let Some(ref current) = progress_bar {
+        current.set_prefix("Encode ");
+    }
+
+    let stdout_task = tokio::spawn(async move {
+        let mut reader = BufReader::new(stdout).lines();
+
+        while let Some(line) = reader.next_line().await.expect("Read line") {
+            if !running_clone.load(Ordering::SeqCst) {
+                break;
+            }
+
+            let mut process = String::new();
+
+            if let Some((key, value)) = line.split_once('=') {
+                process = value.trim().to_string();
+
+                stat_map.insert(key.trim().to_string(), process.clone());
+            }
+
+            if line.starts_with("progress") {
+                let mut progress = FFmpegProgress::new(&stat_map, duration);
+
+                stat_map.clear();
+                stat_map.insert("title".to_string(), title.clone());
+
+                if &process != "end" {
+                    match &app {
+                        Some(a) => {
+                            // all outputs of one process advance together
+                            for t in &titles {
+                                progress.title = t.clone();
+                                a.emit("preset-progress", &progress).expect("Emit progress");
+                            }
+                        }
+                        None => {
+                            if let Some(ref current) = progress_bar {
+                                let msg = if progress.fps > 0.0 {
+                                    format!("{} FPS", progress.fps)
+                                } else if progress.speed > 0.0 {
+                                    format!("{} Speed", progress.speed)
+                                } else {
+                                    String::new()
+                                };
+                                current.set_message(msg);
+                                current.set_position(progress.elapsed_pct);
+                            }
+                        }
+                    };
+                } else if let Some(ref current) = progress_bar {
+                    current.finish_with_message("done...");
+                }
+            }
+        }
+    });
+
+    stderr_task.await?;
+    stdout_task.await?;
+
+    if let Some(proc) = child.lock().await.as_mut() {
+        let status = proc.wait().await?;
+
+        // A killed process of a cancelled task is not an error.
+        if !status.success() && is_running.load(Ordering::SeqCst) {
+            return Err(ProcessError::Custom(format!("ffmpeg failed with {status}")));
+        }
+    }
+
+    Ok(())
+}
+
 pub async fn work(
     app: Option<AppHandle>,
     config: Config,
@@ -103,8 +365,7 @@ pub async fn work(
     is_running: Arc<AtomicBool>,
     task: Task,
     progress_bar: Option<ProgressBar>,
-) -> Result<(), ProcessError> {
-    let app_some = app.is_some();
+) -> Result<Vec<PublishResult>, ProcessError> {
     let mut task_clone = task.clone();
     // let mut presets = mem::take(&mut task_clone.presets);
     let sources = Sources::new(&task.path).await;
@@ -140,45 +401,51 @@ pub async fn work(
         task_args.push("level+warning".to_string());
     }
 
-    let seek = if task.r#in > 0.0 {
-        vec_strings!["-ss", task.r#in]
-    } else {
-        vec![]
-    };
-
-    let length = if task.out > 0.0 {
-        vec_strings!["-t", task.out - task.r#in]
-    } else {
-        vec![]
-    };
-
     let mut audio_path = String::new();
 
     if let Ok(src) = &sources {
         if let Some(video) = &src.video {
             audio_path = video.clone();
             audio_pos += 1;
-            task_args.extend(seek.clone());
-            task_args.extend(vec_strings!["-i", video]);
-            task_args.extend(length.clone());
+            task_args.extend(trimmed_input(&task, video));
         }
 
         if let Some(audio) = &src.audio {
             audio_path = audio.clone();
             audio_pos += 1;
             has_audio = true;
-            task_args.extend(seek.clone());
-            task_args.extend(vec_strings!["-i", audio]);
-            task_args.extend(length.clone());
+            task_args.extend(trimmed_input(&task, audio));
         }
     }
 
+    // Chapters of a downloaded source, cut to the in/out points and moved behind the intro.
+    let chapters = task
+        .metadata
+        .as_ref()
+        .and_then(|m| m.ffmetadata(task.r#in, task.out, intro_dur));
+    let chapters_input = audio_pos + 1;
+    let mut chapters_file = None;
+
+    if let Some(content) = chapters {
+        let file = env::temp_dir().join(format!(
+            "{}.chapters.txt",
+            path.file_stem().unwrap_or_default().to_string_lossy()
+        ));
+        fs::write(&file, content).await?;
+
+        task_args.extend(vec_strings![
+            "-f",
+            "ffmetadata",
+            "-i",
+            file.to_string_lossy()
+        ]);
+        chapters_file = Some(file);
+    }
+
     let mut transcript_src = Some(PathBuf::from(&audio_path));
 
     let lufs = if task.lufs {
-        let mut src_cmd = seek.clone();
-        src_cmd.extend(vec_strings!["-i", audio_path]);
-        src_cmd.extend(length);
+        let src_cmd = trimmed_input(&task, &audio_path);
 
         Lufs::new(
             app.clone(),
@@ -196,37 +463,46 @@ pub async fn work(
     };
 
     if !is_running.load(Ordering::SeqCst) {
-        return Ok(());
+        return Ok(vec![]);
     }
 
-    task_args.extend(vec_strings![
+    let mut metadata = vec_strings![
         "-map_chapters",
-        "-1",
+        if chapters_file.is_some() {
+            chapters_input
+        } else {
+            -1
+        },
         "-map_metadata",
         "-1",
         "-metadata",
         format!("year={year}")
-    ]);
+    ];
 
     if !config.copyright.is_empty() {
-        task_args.extend(vec_strings![
+        metadata.extend(vec_strings![
             "-metadata",
             format!("copyright={}", config.copyright)
         ]);
     }
 
+    if let Some(source) = &task.metadata {
+        metadata.extend(source.args());
+    }
+
+    let mut jobs = Vec::with_capacity(task_clone.presets.len());
+
     for i in 0..task_clone.presets.len() {
-        let preset = task_clone.presets[i].clone();
-        let mut args = task_args.clone();
-        let running = is_running.clone();
-        let running_clone = is_running.clone();
-        let app_clone1 = app.clone();
-        let title = preset.title.clone();
-        let finished = preset.finished.clone();
-        let mut cmd_logger = cmd_logger.clone();
-        let progress_clone = progress_bar.clone();
-        let ff_bin = ff_bin.clone();
+        let Some(preset) = task_clone.presets[i].apply_rules(&task.probe) else {
+            task_clone.presets[i].finished.store(true, Ordering::SeqCst);
 
+            if let Some(a) = &app {
+                a.emit("preset-finish", &task_clone.presets[i])
+                    .expect("Emit progress");
+            }
+
+            continue;
+        };
         let parent_path = path.parent().expect("Path should have a parent");
         let file_stem = path
             .file_stem()
@@ -238,15 +514,6 @@ pub async fn work(
             .or(preset.container_audio.clone())
             .unwrap_or_default();
 
-        if let Some(input_args) = &preset.input {
-            let i_args = split(input_args).unwrap_or_default();
-
-            if let Some(pos) = args.iter().position(|x| x == "-i") {
-                // Insert before input "-i"
-                args.splice(pos..pos, i_args);
-            }
-        }
-
         let file_name = format!("{} # {}.{}", file_stem, preset.title, extension);
 
         let output = match task.target.as_ref() {
@@ -283,197 +550,116 @@ pub async fn work(
         let temp_out = env::temp_dir().join(&file_name);
         task_clone.presets[i].output_path = Some(output.clone());
 
-        if sources.as_ref().map(|s| s.video.clone()).is_ok()
-            && let Value::Object(map) = &preset.video
-            && !map.is_empty()
-        {
+        if sources.as_ref().map(|s| s.video.clone()).is_ok() && !is_empty(&preset.video) {
             has_video = true;
         }
 
-        let mut filter = filter_chain(
+        let filter = filter_chain(
             &config, &task, &preset, &lufs, has_audio, has_video, audio_pos,
         )
         .await;
-        args.extend(filter.cmd());
 
-        if let Some(video_ext) = &preset.container_video {
-            if has_video {
-                args.extend(filter.map_video());
-                args.extend(to_vec(preset.video.clone()));
-            }
-
-            if video_ext.eq_ignore_ascii_case("mp4") {
-                args.extend(vec_strings!["-movflags", "+faststart"]);
-            }
-        }
+        jobs.push(Job {
+            preset,
+            output,
+            temp_out,
+            filter,
+            has_video,
+        });
+    }
 
-        if let Some(audio_ext) = &preset.container_audio {
-            if has_video {
-                args.push(temp_out.to_string_lossy().to_string());
-            }
+    let shared_decode =
+        task.shared_decode && jobs.iter().filter(|j| is_shareable(&j.preset)).count() > 1;
+    let mut groups: Vec<Vec<Job>> = vec![];
 
-            if has_audio {
-                args.extend(filter.map_audio());
-                args.extend(to_vec(preset.audio.clone()));
-            }
+    if shared_decode {
+        let (shared, single): (Vec<Job>, Vec<Job>) =
+            jobs.into_iter().partition(|j| is_shareable(&j.preset));
 
-            args.push(
-                temp_out
-                    .with_extension(audio_ext)
-                    .to_string_lossy()
-                    .to_string(),
-            );
-        } else {
-            if has_audio {
-                args.extend(filter.map_audio());
-                args.extend(to_vec(preset.audio.clone()));
-            }
+        groups.push(shared);
+        groups.extend(single.into_iter().map(|j| vec![j]));
+    } else {
+        groups.extend(jobs.into_iter().map(|j| vec![j]));
+    }
 
-            if has_video {
-                args.push(temp_out.to_string_lossy().to_string());
-            }
+    for mut group in groups {
+        if !is_running.load(Ordering::SeqCst) {
+            break;
         }
 
+        let titles = group
+            .iter()
+            .map(|j| j.preset.title.clone())
+            .collect::<Vec<_>>();
+        let args = group_args(&task_args, &mut group, &metadata, has_audio);
+
         log_command(
-            &format!("Preset: {}", preset.title),
+            &format!("Preset: {}", titles.join(", ")),
             Some(ff_bin.to_string_lossy().to_string()),
             args.clone(),
         );
 
         if let Some(a) = &app {
-            a.emit("preset-start", &preset).expect("Emit Preset");
-        }
-
-        let mut cmd = Command::new(ff_bin);
-
-        cmd.args(args).stderr(Stdio::piped()).stdout(Stdio::piped());
-
-        #[cfg(target_os = "macos")]
-        cmd.env("PATH", MACOS_PATH);
-
-        #[cfg(target_os = "windows")]
-        cmd.creation_flags(0x08000000);
-
-        let mut proc = cmd.spawn()?;
-
-        let stderr = proc.stderr.take().expect("Failed to capture stderr");
-        let stdout = proc.stdout.take().expect("Failed to capture stdout");
-
-        *child.lock().await = Some(proc);
-
-        let stderr_task = tokio::spawn(async move {
-            let mut reader = BufReader::new(stderr).lines();
-
-            while let Some(line) = reader.next_line().await.expect("Read line") {
-                if !running.load(Ordering::SeqCst) {
-                    break;
-                }
-
-                if !IGNORE_LINES.iter().any(|&s| line.contains(s))
-                    && !line.is_empty()
-                    && !line.starts_with("encoded")
-                    && (app_some || !line.contains("[info]"))
-                {
-                    cmd_logger.log(Some("[ffmpeg]"), &line);
-                }
+            for job in &group {
+                a.emit("preset-start", &job.preset).expect("Emit Preset");
             }
-        });
-
-        let mut stat_map = HashMap::new();
-        stat_map.insert("title".to_string(), title.clone());
-
-        if let Some(ref current) = progress_clone {
-            current.set_prefix("Encode ");
         }
 
-        let stdout_task = tokio::spawn(async move {
-            let mut reader = BufReader::new(stdout).lines();
-
-            while let Some(line) = reader.next_line().await.expect("Read line") {
-                if !running_clone.load(Ordering::SeqCst) {
-                    break;
-                }
-
-                let mut process = String::new();
-
-                if let Some((key, value)) = line.split_once('=') {
-                    process = value.trim().to_string();
+        encode(
+            app.clone(),
+            &ff_bin,
+            args,
+            titles,
+            duration,
+            child.clone(),
+            is_running.clone(),
+            cmd_logger.clone(),
+            progress_bar.clone(),
+        )
+        .await?;
 
-                    stat_map.insert(key.trim().to_string(), process.clone());
-                }
+        for job in group {
+            let mut output_files = Vec::with_capacity(2);
 
-                if line.starts_with("progress") {
-                    let progress = FFmpegProgress::new(&stat_map, duration);
-
-                    stat_map.clear();
-                    stat_map.insert("title".to_string(), title.clone());
-
-                    if &process != "end" {
-                        match &app_clone1 {
-                            Some(a) => a.emit("preset-progress", &progress).expect("Emit progress"),
-
-                            None => {
-                                if let Some(ref current) = progress_clone {
-                                    let msg = if progress.fps > 0.0 {
-                                        format!("{} FPS", progress.fps)
-                                    } else if progress.speed > 0.0 {
-                                        format!("{} Speed", progress.speed)
-                                    } else {
-                                        String::new()
-                                    };
-                                    current.set_message(msg);
-                                    current.set_position(progress.elapsed_pct);
-                                }
-                            }
-                        };
-                    } else if let Some(ref current) = progress_clone {
-                        current.finish_with_message("done...");
-                    }
-                }
+            if job.has_video && job.preset.container_video.is_some() {
+                output_files.push((job.temp_out.clone(), job.output.clone()));
             }
-        });
 
-        stderr_task.await?;
-        stdout_task.await?;
-
-        if let Some(proc) = child.lock().await.as_mut() {
-            proc.wait().await?;
-        }
-
-        let mut output_files = Vec::with_capacity(2);
+            if has_audio && let Some(audio_ext) = &job.preset.container_audio {
+                output_files.push((
+                    job.temp_out.with_extension(audio_ext),
+                    job.output.with_extension(audio_ext),
+                ));
+            }
 
-        if has_video && preset.container_video.is_some() {
-            output_files.push((temp_out.clone(), output.clone()));
-        }
+            for (temp_file, output_file) in output_files {
+                fs::copy(&temp_file, &output_file).await?;
+                fs::remove_file(temp_file).await?;
+            }
 
-        if has_audio && let Some(audio_ext) = &preset.container_audio {
-            output_files.push((
-                temp_out.with_extension(audio_ext),
-                output.with_extension(audio_ext),
-            ));
-        }
+            job.preset.finished.store(true, Ordering::SeqCst);
 
-        for (temp_file, output_file) in output_files {
-            fs::copy(&temp_file, &output_file).await?;
-            fs::remove_file(temp_file).await?;
+            if let Some(a) = &app {
+                a.emit("preset-finish", &job.preset).expect("Emit progress");
+            }
         }
+    }
 
-        finished.store(true, Ordering::SeqCst);
-
-        if let Some(a) = &app {
-            a.emit("preset-finish", &preset).expect("Emit progress");
-        }
+    if let Some(file) = chapters_file {
+        fs::remove_file(file).await?;
     }
 
+    let mut captions = None;
+
     if let Some(src) = transcript_src
         && task
             .transcript
             .as_ref()
             .is_some_and(|lang| lang.to_lowercase() != "none")
     {
-        transcript::run(
+        captions = transcript::run(
             app.clone(),
-            config,
+            config.clone(),
             child.clone(),
             is_running.clone(),
             cmd_logger.clone(),
@@ -486,26 +672,32 @@ pub async fn work(
 
     *child.lock().await = None;
 
-    // if task.publish.is_some() && is_running.load(Ordering::SeqCst) {
-    //     publisher::peertube::publish(app, &task_clone, is_running).await?;
-    // }
+    let results =
+        publisher::publish(app, &config, &task_clone, captions.as_deref(), is_running).await;
 
-    Ok(())
+    Ok(results)
 }
 
 pub async fn run(app: AppHandle, mut rx: Receiver<Task>) -> Result<(), ProcessError> {
     let state = app.state::<AppState>().to_owned();
 
-    while let Some(task) = rx.recv().await {
+    while let Some(mut task) = rx.recv().await {
         // Load config for each task to get the latest configuration
         let config = state.config.lock().await.clone();
+        let config = match &task.profile {
+            Some(name) => config.with_profile(name).unwrap_or_else(|e| {
+                error!("{e}");
+                config.active()
+            }),
+            None => config.active(),
+        };
 
         task.active.store(true, Ordering::SeqCst);
 
         if !task.presets.is_empty() || task.transcript.as_ref().is_some_and(|t| t != "none") {
             app.emit("task-active", &task)?;
 
-            work(
+            match work(
                 Some(app.clone()),
                 config.clone(),
                 state.encoder.clone(),
@@ -513,7 +705,22 @@ pub async fn run(app: AppHandle, mut rx: Receiver<Task>) -> Result<(), ProcessEr
                 task.clone(),
                 None,
             )
-            .await?;
+            .await
+            {
+                Ok(results) => task.publish_results = results,
+                Err(e) => {
+                    error!("Encoding of {:?} failed: {e}", task.path);
+
+                    app.emit(
+                        "task-error",
+                        TaskError {
+                            id: task.id.clone(),
+                            message: e.to_string(),
+                        },
+                    )?;
+                }
+            }
+
             task.active.store(false, Ordering::SeqCst);
             task.finished.store(true, Ordering::SeqCst);
 
@@ -527,3 +734,109 @@ pub async fn run(app: AppHandle, mut rx: Receiver<Task>) -> Result<(), ProcessEr
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn shared_graph_defines_mapped_labels() {
+        let config = Config::default();
+        let task = Task::default();
+        let mut group = vec![];
+
+        for (i, scale) in ["1280:720", "640:360"].iter().enumerate() {
+            let preset = Preset {
+                name: format!("preset_{i}"),
+                filter_video: json!({ "scale": scale }),
+                filter_audio: json!({ "volume": "0.5" }),
+                video: json!({ "-c:v": "libx264" }),
+                audio: json!({ "-c:a": "aac" }),
+                container_video: Some("mp4".to_string()),
+                ..Default::default()
+            };
+            let filter =
+                filter_chain(&config, &task, &preset, &Lufs::default(), true, true, 0).await;
+
+            group.push(Job {
+                preset,
+                output: PathBuf::from(format!("out_{i}.mp4")),
+                temp_out: PathBuf::from(format!("temp_{i}.mp4")),
+                filter,
+                has_video: true,
+            });
+        }
+
+        let graph = shared_graph(&mut group).unwrap();
+        let mut mapped = 0;
+
+        for job in &mut group {
+            let args = job.output_args(true);
+
+            for label in args
+                .windows(2)
+                .filter(|pair| pair[0] == "-map" && pair[1].starts_with('['))
+                .map(|pair| &pair[1])
+            {
+                assert_eq!(
+                    graph.matches(label.as_str()).count(),
+                    1,
+                    "{label} in {graph}"
+                );
+                mapped += 1;
+            }
+        }
+
+        assert_eq!(mapped, 4);
+    }
+
+    #[tokio::test]
+    async fn shared_outputs_are_trimmed() {
+        let config = Config::default();
+        let task = Task {
+            r#in: 10.0,
+            out: 25.0,
+            ..Default::default()
+        };
+        let mut group = vec![];
+
+        for i in 0..2 {
+            let preset = Preset {
+                name: format!("preset_{i}"),
+                filter_video: json!({ "scale": "1280:720" }),
+                video: json!({ "-c:v": "libx264" }),
+                audio: json!({ "-c:a": "aac" }),
+                container_video: Some("mp4".to_string()),
+                ..Default::default()
+            };
+            let filter =
+                filter_chain(&config, &task, &preset, &Lufs::default(), true, true, 0).await;
+
+            group.push(Job {
+                preset,
+                output: PathBuf::from(format!("out_{i}.mp4")),
+                temp_out: PathBuf::from(format!("temp_{i}.mp4")),
+                filter,
+                has_video: true,
+            });
+        }
+
+        let task_args = trimmed_input(&task, "source.mov");
+        let args = group_args(&task_args, &mut group, &[], true);
+        let input = args.iter().position(|a| a == "-i").unwrap();
+
+        assert_eq!(
+            args[..input],
+            vec_strings!["-ss", 10.0, "-t", 15.0][..],
+            "{args:?}"
+        );
+        // No output option cuts only the first output.
+        assert!(!args[input..].contains(&"-t".to_string()), "{args:?}");
+
+        for i in 0..2 {
+            assert!(args.contains(&format!("temp_{i}.mp4")), "{args:?}");
+        }
+    }
+}
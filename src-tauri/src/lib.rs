@@ -13,9 +13,9 @@ use serde_with::{NoneAsEmptyString, serde_as};
 // use tauri::Listener;
 // use tauri::Manager;
 use log::*;
-use serde_json::{Value, json};
+use serde_json::{Map, Value};
 use tauri::{
-    AppHandle, Emitter, Manager, State, WindowEvent,
+    AppHandle, Manager, State, WindowEvent,
     menu::{Menu, MenuItem},
     tray::TrayIconBuilder,
 };
@@ -37,17 +37,25 @@ mod publisher;
 mod transcript;
 mod utils;
 
-pub use publisher::Publish;
+pub use publisher::{Publish, PublishResult};
 pub use utils::{
-    Sources, copy_assets, delete_files, download,
+    Sources,
+    backup::{Backup, BackupEntry, BackupPreview},
+    config::{Config, DEFAULT_DOWNLOAD_ARGS, LangConfig, LufsConfig, REMOVED_FIELDS},
+    copy_assets, delete_files, download,
     errors::ProcessError,
     logging::init_logging,
-    presets::{Preset, collect_presets},
-    template::Template,
+    metadata::SourceMetadata,
+    presets::{
+        Preset, collect_presets, delete_preset, duplicate_preset, export_presets, import_presets,
+        preset_path, rename_preset,
+    },
+    template::{Template, template_names},
     update,
 };
 
 use ffmpeg::{probe::MediaProbe, worker};
+use utils::download_queue::{self, DownloadQueue};
 
 #[cfg(target_os = "macos")]
 const MACOS_PATH: &str = "/opt/homebrew/bin:/usr/local/bin:/usr/bin:/bin:/usr/sbin:/sbin";
@@ -61,6 +69,15 @@ pub struct Task {
     pub path: String,
     #[serde(default)]
     pub url: Option<String>,
+    /// Download all items of a playlist URL, also when it names a video.
+    #[serde(default)]
+    pub playlist: bool,
+    /// Item range of a playlist URL, like `1-5,8`.
+    #[serde(default)]
+    pub playlist_items: Option<String>,
+    /// Expected hash of a direct download, like `sha256:<hex>`.
+    #[serde(default)]
+    pub checksum: Option<String>,
     pub r#in: f64,
     pub out: f64,
     pub fade: bool,
@@ -79,6 +96,18 @@ pub struct Task {
     pub target_subfolder: bool,
     #[serde(default)]
     pub publish: Option<Publish>,
+    /// Uploads of the last run.
+    #[serde(default)]
+    pub publish_results: Vec<PublishResult>,
+    /// Metadata of a downloaded source, like title and chapters.
+    #[serde(default)]
+    pub metadata: Option<SourceMetadata>,
+    /// Encode all compatible presets in one ffmpeg process, sharing the decoder.
+    #[serde(default)]
+    pub shared_decode: bool,
+    /// Config profile the task is encoded with, the active profile if not set.
+    #[serde(default)]
+    pub profile: Option<String>,
     #[ts(type = "bool")]
     pub active: Arc<AtomicBool>,
     #[ts(type = "bool")]
@@ -90,75 +119,20 @@ struct AppState {
     run: Arc<AtomicBool>,
     sender: Sender<Task>,
     encoder: Arc<Mutex<Option<Child>>>,
-    downloader: Arc<Mutex<Option<Child>>>,
+    download_sender: Sender<Task>,
+    downloads: DownloadQueue,
     config: Arc<Mutex<Config>>,
 }
 
-#[derive(Debug, Clone, Default, Deserialize, Serialize, TS)]
-#[ts(export, export_to = "backend.d.ts")]
-pub struct Config {
-    pub copyright: String,
-    pub ffmpeg_path: Option<PathBuf>,
-    #[serde(default)]
-    pub download_path: Option<PathBuf>,
-    #[serde(default)]
-    pub download_args: String,
-    #[serde(default)]
-    pub yt_dlp_path: Option<PathBuf>,
-    pub lufs: LufsConfig,
-    pub transcript_cmd: String,
-    pub transcript_lang: Vec<LangConfig>,
-    #[serde(default)]
-    pub publish_preset: Option<String>,
-    #[serde(default)]
-    pub publisher: Option<Value>,
-}
-
-const DEFAULT_DOWNLOAD_ARGS: &str = "--output \"%(title)s.%(ext)s\"";
-
-impl Config {
-    pub fn code_from(&self, lang: &str) -> String {
-        self.transcript_lang
-            .iter()
-            .find(|l| l.name.eq_ignore_ascii_case(lang) || l.code.eq_ignore_ascii_case(lang))
-            .map(|l| l.code.clone())
-            .unwrap_or_else(|| lang.to_string())
-    }
-}
-
-#[derive(Debug, Clone, Default, Deserialize, Serialize, TS)]
-#[ts(export, export_to = "backend.d.ts")]
-pub struct LangConfig {
-    pub name: String,
-    pub code: String,
-}
-
-#[derive(Debug, Clone, Default, Deserialize, Serialize, TS)]
-#[ts(export, export_to = "backend.d.ts")]
-pub struct LufsConfig {
-    pub i: f64,
-    pub lra: f64,
-    pub tp: f64,
-}
-
 impl AppState {
-    fn new(tx: Sender<Task>) -> Self {
+    fn new(tx: Sender<Task>, download_tx: Sender<Task>) -> Self {
         Self {
             run: Arc::new(AtomicBool::new(false)),
             sender: tx,
             encoder: Arc::new(Mutex::new(None)),
-            downloader: Arc::new(Mutex::new(None)),
-            config: Arc::new(Mutex::new(Config {
-                lufs: LufsConfig {
-                    i: -17.0,
-                    lra: 9.0,
-                    tp: -1.0,
-                },
-
-                transcript_cmd: String::new(),
-                download_args: DEFAULT_DOWNLOAD_ARGS.to_string(),
-                ..Default::default()
-            })),
+            download_sender: download_tx,
+            downloads: DownloadQueue::default(),
+            config: Arc::new(Mutex::new(Config::default())),
         }
     }
 }
@@ -166,7 +140,7 @@ impl AppState {
 impl Drop for AppState {
     fn drop(&mut self) {
         let encoder = self.encoder.clone();
-        let downloader = self.downloader.clone();
+        let downloader = self.downloads.process.clone();
         self.run.store(false, Ordering::SeqCst);
 
         tokio::spawn(async move {
@@ -185,10 +159,13 @@ impl Drop for AppState {
 }
 
 #[tauri::command]
-async fn presets_get(app: AppHandle) -> tauri::Result<Vec<Preset>> {
-    let presets = collect_presets(&Some(app))
-        .await
-        .map_err(|e| tauri::Error::AssetNotFound(e.to_string()))?;
+async fn presets_get(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    path: Option<PathBuf>,
+) -> Result<Vec<Preset>, ProcessError> {
+    let config = state.config.lock().await.clone();
+    let presets = collect_presets(&Some(app), &config, path.as_deref()).await?;
 
     Ok(presets)
 }
@@ -215,45 +192,30 @@ async fn yt_dlp_version(state: State<'_, AppState>) -> Result<String, ProcessErr
     download::version(path).await
 }
 
+/// Queue a task with an URL, it is downloaded and then sent to the encode queue.
 #[tauri::command]
-async fn download_url(
-    app: AppHandle,
-    state: State<'_, AppState>,
-    url: String,
-    target: Option<String>,
-) -> Result<String, ProcessError> {
-    if !url.starts_with("http://") && !url.starts_with("https://") {
+async fn task_download(task: Task, state: State<'_, AppState>) -> Result<(), ProcessError> {
+    if !task.url.as_deref().is_some_and(download::is_url) {
         return Err(ProcessError::Custom(
             "Please enter a valid http(s) URL".into(),
         ));
     }
 
-    let config = state.config.lock().await;
-    let configured_path = config.download_path.clone();
-    let yt_dlp_path = config.yt_dlp_path.clone();
-    let arguments = if config.download_args.trim().is_empty() {
-        DEFAULT_DOWNLOAD_ARGS.to_string()
-    } else {
-        config.download_args.clone()
-    };
-    drop(config);
-    let directory = resolve_download_directory(target, configured_path, dirs::download_dir())
-        .ok_or_else(|| ProcessError::Custom("Could not determine the Downloads folder".into()))?;
-
-    app.emit("download-start", &url)
-        .map_err(|error| ProcessError::Custom(error.to_string()))?;
-    let file = download::download(
-        app.clone(),
-        url,
-        directory,
-        &arguments,
-        yt_dlp_path,
-        state.downloader.clone(),
-    )
-    .await?;
-    app.emit("download-finish", &file)
-        .map_err(|error| ProcessError::Custom(error.to_string()))?;
-    Ok(file)
+    let id = task.id.clone();
+    state.downloads.queue(&id).await;
+
+    if let Err(e) = state.download_sender.send(task).await {
+        state.downloads.unqueue(&id).await;
+
+        return Err(e.into());
+    }
+
+    Ok(())
+}
+
+#[tauri::command]
+async fn download_cancel(id: String, state: State<'_, AppState>) -> Result<(), ProcessError> {
+    state.downloads.cancel(&id).await
 }
 
 #[tauri::command]
@@ -273,18 +235,13 @@ async fn task_send(task: Task, state: State<'_, AppState>) -> Result<(), Process
 #[tauri::command]
 async fn task_cancel(task: Task, state: State<'_, AppState>) -> Result<(), ProcessError> {
     let encoder = state.encoder.clone();
-    let downloader = state.downloader.clone();
     state.run.store(false, Ordering::SeqCst);
 
-    for process in [encoder.clone(), downloader] {
-        if let Some(mut proc) = process.lock().await.take() {
-            proc.kill().await?;
-            proc.wait().await?;
-        }
+    if let Some(mut proc) = encoder.lock().await.take() {
+        proc.kill().await?;
+        proc.wait().await?;
     }
 
-    *encoder.lock().await = None;
-
     for preset in task.presets {
         if let Some(path) = preset.output_path {
             match delete_files(&path).await {
@@ -304,25 +261,142 @@ async fn template_save(template: Template, path: &str) -> Result<(), ProcessErro
     Ok(())
 }
 
+#[tauri::command]
+async fn template_list(app: AppHandle) -> Result<Vec<String>, ProcessError> {
+    template_names(&Some(app)).await
+}
+
+#[tauri::command]
+async fn template_load(app: AppHandle, name: String) -> Result<Template, ProcessError> {
+    Template::named(&Some(app), &name).await
+}
+
+#[tauri::command]
+async fn template_store(
+    app: AppHandle,
+    template: Template,
+    name: String,
+) -> Result<(), ProcessError> {
+    template.save_named(&Some(app), &name).await
+}
+
 #[tauri::command]
 async fn save_preset(app: AppHandle, mut preset: Preset) -> Result<(), ProcessError> {
-    let preset_path = app.path().app_data_dir()?.join("presets");
+    let preset_path = preset_path(&Some(app))?;
 
     if !preset_path.is_dir() {
-        tokio::fs::create_dir(&preset_path).await?;
+        tokio::fs::create_dir_all(&preset_path).await?;
     }
 
     preset.save(&preset_path).await
 }
 
-fn prep_ffmpeg_path(config: &mut Config) {
-    if let Some(ffmpeg_path) = &config.ffmpeg_path {
-        if ffmpeg_path.is_file() {
-            config.ffmpeg_path = ffmpeg_path.parent().map(|p| p.to_path_buf());
-        } else if !ffmpeg_path.is_dir() {
-            config.ffmpeg_path = None;
-        }
+#[tauri::command]
+async fn preset_delete(app: AppHandle, name: String) -> Result<(), ProcessError> {
+    delete_preset(&Some(app), &name).await
+}
+
+#[tauri::command]
+async fn preset_rename(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    name: String,
+    new_name: String,
+) -> Result<(), ProcessError> {
+    let config = state.config.lock().await.clone();
+
+    rename_preset(&Some(app), &config, &name, &new_name).await
+}
+
+#[tauri::command]
+async fn preset_duplicate(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    name: String,
+    new_name: Option<String>,
+) -> Result<String, ProcessError> {
+    let config = state.config.lock().await.clone();
+
+    duplicate_preset(&Some(app), &config, &name, new_name.as_deref()).await
+}
+
+#[tauri::command]
+async fn preset_import(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    path: PathBuf,
+    overwrite: bool,
+) -> Result<Vec<String>, ProcessError> {
+    let config = state.config.lock().await.clone();
+
+    import_presets(&Some(app), &config, &path, overwrite).await
+}
+
+#[tauri::command]
+async fn preset_export(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    names: Vec<String>,
+    path: PathBuf,
+) -> Result<(), ProcessError> {
+    let config = state.config.lock().await.clone();
+
+    export_presets(&Some(app), &config, &names, &path).await
+}
+
+fn store_config(app: &AppHandle) -> Result<Map<String, Value>, ProcessError> {
+    let store = app.store("config.json")?;
+
+    Ok(store.entries().into_iter().collect())
+}
+
+#[tauri::command]
+async fn backup_export(app: AppHandle, path: PathBuf, secrets: bool) -> Result<(), ProcessError> {
+    let config = store_config(&app)?;
+
+    Backup::collect(&Some(app), config, secrets)
+        .await?
+        .write(&path)
+        .await
+}
+
+#[tauri::command]
+async fn backup_preview(app: AppHandle, path: PathBuf) -> Result<BackupPreview, ProcessError> {
+    let config = store_config(&app)?;
+
+    Backup::read(&path)
+        .await?
+        .preview(&Some(app), &config)
+        .await
+}
+
+#[tauri::command]
+async fn backup_import(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    path: PathBuf,
+    overwrite: bool,
+) -> Result<Vec<BackupEntry>, ProcessError> {
+    let backup = Backup::read(&path).await?;
+    let store = app.store("config.json")?;
+    let mut config = store_config(&app)?;
+    let mut restored = backup.merge_config(&mut config, overwrite);
+
+    // An invalid backup leaves the store unchanged, it would fail on the next start.
+    Config::from_value(Value::Object(config.clone()))?
+        .0
+        .validate()?;
+
+    for (key, value) in config {
+        store.set(key, value);
     }
+
+    store.save()?;
+    restored.extend(backup.restore_files(&Some(app.clone()), overwrite).await?);
+
+    save_config(app, state).await?;
+
+    Ok(restored)
 }
 
 fn resolve_download_directory(
@@ -337,114 +411,83 @@ fn resolve_download_directory(
         .or(fallback)
 }
 
-fn non_empty_path(path: &str) -> Option<PathBuf> {
-    (!path.trim().is_empty()).then(|| PathBuf::from(path))
-}
-
-#[tauri::command]
-async fn save_config(app: AppHandle, state: State<'_, AppState>) -> Result<(), ProcessError> {
+/// Read the config from the tauri store, migrated configs are written back.
+fn config_from_store(app: &AppHandle) -> Result<Config, ProcessError> {
     let store = app.store("config.json")?;
-    let mut config = state.config.lock().await;
-
-    if let Some(Value::String(ffmpeg_path)) = store.get("ffmpeg_path") {
-        config.ffmpeg_path = Some(PathBuf::from(ffmpeg_path));
-    }
-    if let Some(Value::String(download_path)) = store.get("download_path") {
-        config.download_path = Some(PathBuf::from(download_path));
-    }
-    if let Some(Value::String(download_args)) = store.get("download_args") {
-        config.download_args = download_args;
-    }
-    if let Some(Value::String(yt_dlp_path)) = store.get("yt_dlp_path") {
-        config.yt_dlp_path = non_empty_path(&yt_dlp_path);
-    }
+    let (config, migrated) = Config::from_value(Value::Object(store_config(app)?))?;
 
-    if let Some(Value::String(copyright)) = store.get("copyright") {
-        config.copyright = copyright;
-    }
+    if migrated {
+        for (key, value) in config.to_map()? {
+            store.set(key, value);
+        }
 
-    if let Some(l) = store.get("lufs") {
-        config.lufs.i = l.get("i").and_then(|l| l.as_f64()).unwrap_or_default();
-        config.lufs.lra = l.get("lra").and_then(|l| l.as_f64()).unwrap_or_default();
-        config.lufs.tp = l.get("tp").and_then(|l| l.as_f64()).unwrap_or_default();
-    }
+        for key in REMOVED_FIELDS {
+            store.delete(key);
+        }
 
-    if let Some(Value::String(s)) = store.get("transcript_cmd") {
-        config.transcript_cmd = s;
+        store.save()?;
     }
 
-    prep_ffmpeg_path(&mut config);
     store.close_resource();
 
-    Ok(())
+    Ok(config)
 }
 
+/// The config with the values of the active profile.
 #[tauri::command]
-async fn load_config(app: AppHandle, state: State<'_, AppState>) -> Result<(), ProcessError> {
-    let app_handle = app.app_handle();
-
-    copy_assets(app_handle).await.expect("Copy assets");
+async fn config_get(state: State<'_, AppState>) -> Result<Config, ProcessError> {
+    Ok(state.config.lock().await.active())
+}
 
-    let store = app_handle.store("config.json").expect("Open config");
+/// Activate a profile, `None` uses the base values.
+#[tauri::command]
+async fn profile_select(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    name: Option<String>,
+) -> Result<Config, ProcessError> {
     let mut config = state.config.lock().await;
 
-    if let Some(Value::String(ffmpeg_path)) = store.get("ffmpeg_path") {
-        config.ffmpeg_path = Some(PathBuf::from(ffmpeg_path));
-    }
-    if let Some(Value::String(download_path)) = store.get("download_path") {
-        config.download_path = Some(PathBuf::from(download_path));
-    }
-    if let Some(Value::String(download_args)) = store.get("download_args") {
-        config.download_args = download_args;
-    }
-    if let Some(Value::String(yt_dlp_path)) = store.get("yt_dlp_path") {
-        config.yt_dlp_path = non_empty_path(&yt_dlp_path);
+    if let Some(name) = &name {
+        config.with_profile(name)?;
     }
 
-    if let Some(Value::String(copyright)) = store.get("copyright") {
-        config.copyright = copyright;
-    }
+    let store = app.store("config.json")?;
+    store.set("profile", serde_json::to_value(&name)?);
+    store.save()?;
+    store.close_resource();
 
-    if let Some(Value::String(publish_preset)) = store.get("publish_preset") {
-        config.publish_preset = Some(publish_preset);
-    }
+    config.profile = name;
 
-    config.publisher = store.get("publisher");
+    Ok(config.active())
+}
 
-    match store.get("lufs") {
-        Some(l) => {
-            config.lufs.i = l.get("i").and_then(|l| l.as_f64()).unwrap_or_default();
-            config.lufs.lra = l.get("lra").and_then(|l| l.as_f64()).unwrap_or_default();
-            config.lufs.tp = l.get("tp").and_then(|l| l.as_f64()).unwrap_or_default();
-        }
-        None => {
-            store.set("lufs", json!({ "i": -17.0, "lra": 9.0, "tp": -1.0 }));
-            store.save().expect("Save config");
+#[tauri::command]
+async fn save_config(app: AppHandle, state: State<'_, AppState>) -> Result<(), ProcessError> {
+    let mut config = config_from_store(&app)?;
 
-            config.lufs.i = -17.0;
-            config.lufs.lra = 9.0;
-            config.lufs.tp = -1.0;
-        }
-    }
+    config.validate()?;
+    config.normalize();
 
-    match store.get("transcript_cmd") {
-        Some(t) => {
-            if let Value::String(s) = t {
-                config.transcript_cmd = s;
-            }
-        }
-        None => {
-            store.set("transcript_cmd", "");
-            store.save().expect("Save config");
-        }
-    }
-
-    prep_ffmpeg_path(&mut config);
-    store.close_resource();
+    *state.config.lock().await = config;
 
     Ok(())
 }
 
+#[tauri::command]
+async fn load_config(app: AppHandle, state: State<'_, AppState>) -> Result<(), ProcessError> {
+    copy_assets(&app).await.expect("Copy assets");
+
+    let mut config = config_from_store(&app)?;
+    let valid = config.validate();
+
+    // Start with the usable values, the error is shown in the frontend.
+    config.normalize();
+    *state.config.lock().await = config;
+
+    valid
+}
+
 #[tauri::command]
 fn shutdown_system() -> Result<(), ProcessError> {
     system_shutdown::shutdown()?;
@@ -455,6 +498,7 @@ fn shutdown_system() -> Result<(), ProcessError> {
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub async fn run() -> tauri::Result<()> {
     let (tx, rx) = channel(5);
+    let (download_tx, download_rx) = channel(100);
 
     tauri::Builder::default()
         .plugin(tauri_plugin_clipboard_manager::init())
@@ -475,7 +519,7 @@ pub async fn run() -> tauri::Result<()> {
         })
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_shell::init())
-        .manage(AppState::new(tx))
+        .manage(AppState::new(tx, download_tx))
         .setup(|app| {
             let app_handle = app.app_handle().clone();
             let app_handle_clone = app_handle.clone();
@@ -507,6 +551,14 @@ pub async fn run() -> tauri::Result<()> {
                 };
             });
 
+            let download_handle = app_handle.clone();
+
+            tokio::spawn(async move {
+                if let Err(e) = download_queue::run(download_handle, download_rx).await {
+                    error!("{e:?}");
+                };
+            });
+
             let _ = TrayIconBuilder::new()
                 .menu(&menu)
                 .show_menu_on_left_click(true)
@@ -527,7 +579,7 @@ pub async fn run() -> tauri::Result<()> {
             WindowEvent::CloseRequested { .. } => {
                 let app_state = window.state::<AppState>();
                 let encoder = app_state.encoder.clone();
-                let downloader = app_state.downloader.clone();
+                let downloader = app_state.downloads.process.clone();
 
                 tokio::spawn(async move {
                     for process in [encoder, downloader] {
@@ -557,14 +609,28 @@ pub async fn run() -> tauri::Result<()> {
         .invoke_handler(tauri::generate_handler![
             file_drop,
             yt_dlp_version,
-            download_url,
+            task_download,
+            download_cancel,
             presets_get,
             task_start,
             task_send,
             task_cancel,
             template_save,
+            template_list,
+            template_load,
+            template_store,
             save_config,
+            config_get,
+            profile_select,
             save_preset,
+            preset_delete,
+            preset_rename,
+            preset_duplicate,
+            preset_import,
+            preset_export,
+            backup_export,
+            backup_preview,
+            backup_import,
             shutdown_system,
             load_config,
         ])
@@ -600,13 +666,4 @@ mod download_path_tests {
             Some(PathBuf::from("/fallback"))
         );
     }
-
-    #[test]
-    fn ignores_an_empty_yt_dlp_path() {
-        assert_eq!(non_empty_path("  "), None);
-        assert_eq!(
-            non_empty_path("/opt/homebrew/bin/yt-dlp"),
-            Some(PathBuf::from("/opt/homebrew/bin/yt-dlp"))
-        );
-    }
 }
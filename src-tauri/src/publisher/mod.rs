@@ -1,8 +1,36 @@
+use std::{
+    future::Future,
+    path::Path,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use async_stream::try_stream;
+use log::*;
 use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
+use tauri::{AppHandle, Emitter, Manager};
+use tauri_plugin_store::StoreExt;
+use tokio::io::{self, AsyncRead, AsyncReadExt};
+use tokio_stream::Stream;
 use ts_rs::TS;
 
-// pub mod peertube;
+use crate::{AppState, Config, Preset, ProcessError, Task};
+
+pub mod peertube;
+pub mod s3;
+pub mod tus;
+pub mod webdav;
+
+/// Read size of upload streams.
+const BUFFER_SIZE: usize = 1024 * 1024;
+
+use peertube::PeerTubeConfig;
+use s3::S3Config;
+use tus::{TusConfig, TusPublish};
+use webdav::WebDavConfig;
 
 #[serde_as]
 #[derive(Clone, Debug, Default, Deserialize, Serialize, TS)]
@@ -12,4 +40,365 @@ pub struct Publish {
     pub thumbnail: String,
     pub description: String,
     pub tags: String,
+    /// Endpoint, headers and metadata of tus targets for this task.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tus: Option<TusPublish>,
+}
+
+#[derive(Clone, Debug, Serialize, TS)]
+#[ts(export, export_to = "backend.d.ts")]
+pub struct UploadProgress {
+    pub target: String,
+    pub percent: f64,
+}
+
+/// Delivery target of `Config.publish_targets`, the `type` field selects the platform.
+#[derive(Clone, Debug, Deserialize, Serialize, TS)]
+#[ts(export, export_to = "backend.d.ts")]
+#[serde(tag = "type")]
+pub enum PublishTarget {
+    #[serde(rename = "peertube")]
+    PeerTube(PeerTubeConfig),
+    #[serde(rename = "s3")]
+    S3(S3Config),
+    #[serde(rename = "tus")]
+    Tus(TusConfig),
+    #[serde(rename = "webdav")]
+    WebDav(WebDavConfig),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize, TS)]
+#[ts(export, export_to = "backend.d.ts")]
+#[serde(rename_all = "lowercase")]
+pub enum PublishStatus {
+    Published,
+    Failed,
+    Cancelled,
+}
+
+/// Upload of one preset output to one target.
+#[derive(Clone, Debug, Deserialize, Serialize, TS)]
+#[ts(export, export_to = "backend.d.ts")]
+pub struct PublishResult {
+    pub target: String,
+    pub preset: String,
+    pub status: PublishStatus,
+    /// Link to the published file or video.
+    pub url: Option<String>,
+    pub message: Option<String>,
+}
+
+/// Everything a target needs for one upload.
+pub struct Upload<'a> {
+    pub app: &'a Option<AppHandle>,
+    /// Name of the target in the config.
+    pub target: &'a str,
+    pub task: &'a Task,
+    pub preset: &'a Preset,
+    pub publish: &'a Publish,
+    pub file: &'a Path,
+    /// WebVTT file of the transcript.
+    pub captions: Option<&'a Path>,
+    pub is_running: &'a Arc<AtomicBool>,
+}
+
+impl Upload<'_> {
+    /// Emit the upload progress of the target.
+    pub fn progress(&self, uploaded: u64, total: u64) {
+        emit_progress(self.app, self.target, uploaded, total);
+    }
+
+    /// Progress callback without borrows, for request bodies and spawned parts.
+    pub fn progress_fn(&self) -> impl Fn(u64, u64) + Send + Sync + 'static {
+        let app = self.app.clone();
+        let target = self.target.to_string();
+
+        move |uploaded, total| emit_progress(&app, &target, uploaded, total)
+    }
+
+    /// Title of the upload, the file name without a publish name.
+    pub fn name(&self) -> String {
+        if self.publish.name.trim().is_empty() {
+            self.file
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string()
+        } else {
+            self.publish.name.clone()
+        }
+    }
+
+    /// Replace the placeholders of a target setting:
+    /// `{name}`, `{description}`, `{tags}`, `{file}`, `{source}` and `{preset}`.
+    pub fn fill(&self, template: &str) -> String {
+        let file_name = |path: &Path, stem: bool| {
+            let name = if stem {
+                path.file_stem()
+            } else {
+                path.file_name()
+            };
+
+            name.unwrap_or_default().to_string_lossy().to_string()
+        };
+
+        template
+            .replace("{name}", &self.name())
+            .replace("{description}", &self.publish.description)
+            .replace("{tags}", &self.publish.tags)
+            .replace("{file}", &file_name(self.file, false))
+            .replace("{source}", &file_name(Path::new(&self.task.path), true))
+            .replace("{preset}", &self.preset.name)
+    }
+}
+
+fn emit_progress(app: &Option<AppHandle>, target: &str, uploaded: u64, total: u64) {
+    if let Some(app) = app {
+        let progress = UploadProgress {
+            target: target.to_string(),
+            percent: uploaded as f64 / total.max(1) as f64 * 100.0,
+        };
+
+        let _ = app.emit("upload-progress", progress);
+    }
+}
+
+/// Content type of uploaded files.
+pub fn mime_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_lowercase();
+
+    match extension.as_str() {
+        "mp4" | "m4v" => "video/mp4",
+        "webm" => "video/webm",
+        "mkv" => "video/x-matroska",
+        "mov" => "video/quicktime",
+        "ogv" => "video/ogg",
+        "mp3" => "audio/mpeg",
+        "m4a" | "aac" => "audio/mp4",
+        "ogg" | "opus" => "audio/ogg",
+        "flac" => "audio/flac",
+        "wav" => "audio/wav",
+        "vtt" => "text/vtt",
+        _ => "application/octet-stream",
+    }
+}
+
+/// File content in parts, which reports the sent bytes and stops on cancel.
+pub fn file_body(
+    mut file: impl AsyncRead + Unpin + Send + 'static,
+    is_running: Arc<AtomicBool>,
+    progress: impl Fn(u64) + Send + 'static,
+) -> impl Stream<Item = Result<Vec<u8>, io::Error>> + Send + 'static {
+    try_stream! {
+        let mut sent = 0;
+
+        loop {
+            if !is_running.load(Ordering::SeqCst) {
+                Err::<(), _>(io::Error::other("Upload was cancelled"))?;
+            }
+
+            let mut buffer = vec![0; BUFFER_SIZE];
+            let read = file.read(&mut buffer).await?;
+
+            if read == 0 {
+                break;
+            }
+
+            buffer.truncate(read);
+            sent += read as u64;
+            progress(sent);
+
+            yield buffer;
+        }
+    }
+}
+
+/// Platform or storage, which receives encoded files.
+pub trait Publisher {
+    /// Upload the file, returns a link to it when the target has one.
+    ///
+    /// Targets can change their own config, like refreshed tokens, it is saved afterwards.
+    fn publish(
+        &mut self,
+        upload: &Upload<'_>,
+    ) -> impl Future<Output = Result<Option<String>, ProcessError>> + Send;
+}
+
+impl PublishTarget {
+    /// Copy newer tokens into the stored target, when it is still the same account.
+    ///
+    /// Returns if the stored target changed.
+    fn merge_state(&self, stored: &mut Self) -> bool {
+        match (self, stored) {
+            (Self::PeerTube(new), Self::PeerTube(stored))
+                if new.url == stored.url
+                    && new.username == stored.username
+                    && new.timestamp > stored.timestamp =>
+            {
+                stored.client_id = new.client_id.clone();
+                stored.client_secret = new.client_secret.clone();
+                stored.access_token = new.access_token.clone();
+                stored.refresh_token = new.refresh_token.clone();
+                stored.timestamp = new.timestamp;
+                stored.expires_in = new.expires_in;
+                stored.refresh_token_expires_in = new.refresh_token_expires_in;
+
+                true
+            }
+            _ => false,
+        }
+    }
+
+    async fn publish(&mut self, upload: &Upload<'_>) -> Result<Option<String>, ProcessError> {
+        match self {
+            Self::PeerTube(target) => target.publish(upload).await,
+            Self::S3(target) => target.publish(upload).await,
+            Self::Tus(target) => target.publish(upload).await,
+            Self::WebDav(target) => target.publish(upload).await,
+        }
+    }
+}
+
+/// Names of the targets, which receive the output of `preset`.
+///
+/// Without targets in the preset, the publish preset of the config goes to all targets.
+pub fn preset_targets(config: &Config, preset: &Preset) -> Vec<String> {
+    if !preset.publish_targets.is_empty() {
+        preset.publish_targets.clone()
+    } else if config.publish_preset.as_ref() == Some(&preset.name) {
+        config.publish_targets.keys().cloned().collect()
+    } else {
+        vec![]
+    }
+}
+
+/// Write the state a target changed itself, like refreshed tokens, to the config and the tauri store.
+///
+/// Other fields of the stored target stay, they can be edited in the GUI while a task is running.
+/// Deleted targets are not added again.
+async fn save_target(
+    app: &AppHandle,
+    name: &str,
+    target: &PublishTarget,
+) -> Result<(), ProcessError> {
+    let state = app.state::<AppState>();
+    let mut config = state.config.lock().await;
+
+    let Some(stored) = config.publish_targets.get_mut(name) else {
+        return Ok(());
+    };
+
+    if !target.merge_state(stored) {
+        return Ok(());
+    }
+
+    let store = app.store("config.json")?;
+    store.set(
+        "publish_targets",
+        serde_json::to_value(&config.publish_targets)?,
+    );
+    store.save()?;
+    store.close_resource();
+
+    Ok(())
+}
+
+/// Publish the encoded outputs of the task to the targets of their presets.
+///
+/// A failed upload doesn't stop the others, every upload has its result.
+/// Tasks without publish fields, like from the CLI, use the source metadata and the file names.
+pub async fn publish(
+    app: Option<AppHandle>,
+    config: &Config,
+    task: &Task,
+    captions: Option<&Path>,
+    is_running: Arc<AtomicBool>,
+) -> Vec<PublishResult> {
+    let mut results = vec![];
+    let mut targets = config.publish_targets.clone();
+    let publish = match &task.publish {
+        Some(publish) => publish.clone(),
+        None => {
+            let mut publish = Publish::default();
+
+            if let Some(metadata) = &task.metadata {
+                publish.fill(metadata);
+            }
+
+            publish
+        }
+    };
+
+    for preset in &task.presets {
+        let Some(file) = preset.output_path.as_ref().filter(|p| p.is_file()) else {
+            continue;
+        };
+
+        for name in preset_targets(config, preset) {
+            let mut result = PublishResult {
+                target: name.clone(),
+                preset: preset.name.clone(),
+                status: PublishStatus::Published,
+                url: None,
+                message: None,
+            };
+
+            if !is_running.load(Ordering::SeqCst) {
+                result.status = PublishStatus::Cancelled;
+                results.push(result);
+                continue;
+            }
+
+            let Some(target) = targets.get_mut(&name) else {
+                result.status = PublishStatus::Failed;
+                result.message = Some(format!("Unknown publish target \"{name}\""));
+                results.push(result);
+                continue;
+            };
+
+            let upload = Upload {
+                app: &app,
+                target: &name,
+                task,
+                preset,
+                publish: &publish,
+                file,
+                captions,
+                is_running: &is_running,
+            };
+
+            if let Some(a) = &app {
+                let _ = a.emit("upload-start", &name);
+            }
+
+            match target.publish(&upload).await {
+                Ok(url) => result.url = url,
+                Err(e) => {
+                    result.status = if is_running.load(Ordering::SeqCst) {
+                        error!("Publish {file:?} to {name} failed: {e}");
+                        PublishStatus::Failed
+                    } else {
+                        PublishStatus::Cancelled
+                    };
+                    result.message = Some(e.to_string());
+                }
+            }
+
+            if let Some(app) = &app {
+                let _ = app.emit("upload-finish", &name);
+
+                if let Err(e) = save_target(app, &name, target).await {
+                    warn!("Could not save publish target {name}: {e}");
+                }
+            }
+
+            results.push(result);
+        }
+    }
+
+    results
 }
@@ -1,106 +1,442 @@
 use std::{
-    cmp::min,
-    path::PathBuf,
-    sync::{
-        atomic::{AtomicBool, Ordering},
-        Arc,
-    },
+    io::SeekFrom,
+    path::Path,
+    sync::atomic::Ordering,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use log::*;
-use tauri::{AppHandle, Emitter, Manager};
-use tauri_plugin_http::reqwest;
-use tokio_stream::StreamExt;
-use tokio_util::io::ReaderStream;
-
-use crate::{AppState, ProcessError, Task};
-
-pub async fn publish(
-    app: AppHandle,
-    task: &Task,
-    is_running: Arc<AtomicBool>,
-) -> Result<(), ProcessError> {
-    let state = app.state::<AppState>().to_owned();
-    let config = state.config.lock().await.clone();
-    let publisher = config.publisher.clone().ok_or("Get publisher")?;
-    let token_peertube = publisher
-        .get("peertube")
-        .and_then(|p| p.get("access_token"))
-        .ok_or("Peertube token")?;
-    let url_peertube = publisher
-        .get("peertube")
-        .and_then(|p| p.get("url"))
-        .ok_or("Peertube URL")?;
-    let id_peertube = publisher
-        .get("peertube")
-        .and_then(|p| p.get("channel_id"))
-        .ok_or("Peertube URL")?;
-    let mut source = PathBuf::from(&task.path);
-
-    // TODO: add webvtt file
-
-    let publish_name = task.publish.as_ref().unwrap().name.clone();
-    let publish_description = task.publish.as_ref().unwrap().description.clone();
-    let publish_tags = task.publish.as_ref().unwrap().tags.clone();
-
-    for preset in &task.presets {
-        if let Some(publish_preset) = config.publish_preset.clone() {
-            if preset.name == publish_preset {
-                if let Some(output_path) = &preset.output_path {
-                    source = output_path.to_path_buf();
-                }
-            }
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use tauri_plugin_http::reqwest::{
+    Client, RequestBuilder, Response, StatusCode, Url,
+    header::{CONTENT_LENGTH, CONTENT_RANGE, LOCATION, RANGE},
+    multipart::{Form, Part},
+};
+use tokio::{
+    fs::{self, File},
+    io::{AsyncReadExt, AsyncSeekExt},
+};
+
+use ts_rs::TS;
+
+use crate::{
+    ProcessError,
+    publisher::{Publisher, Upload, mime_type},
+};
+
+/// Size of the parts of a resumable upload.
+const CHUNK_SIZE: u64 = 8 * 1024 * 1024;
+/// Tries of one part, before the upload fails.
+const RETRIES: usize = 3;
+const NAME: &str = "PeerTube";
+
+/// PeerTube channel, the GUI writes the tokens on login.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, TS)]
+#[ts(export, export_to = "backend.d.ts")]
+#[serde(default)]
+pub struct PeerTubeConfig {
+    pub url: String,
+    pub channel_id: u64,
+    pub username: String,
+    /// Only needed without a valid token, for example for logins without the GUI.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    pub client_id: String,
+    pub client_secret: String,
+    pub access_token: String,
+    pub refresh_token: String,
+    /// Unix time of the last token request.
+    pub timestamp: f64,
+    pub expires_in: f64,
+    pub refresh_token_expires_in: f64,
+    /// 1 public, 2 unlisted, 3 private, 4 internal.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub privacy: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub category: Option<u32>,
+    /// Language of the videos, also used for captions of auto detected transcripts.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct OAuthClient {
+    client_id: String,
+    client_secret: String,
+}
+
+#[derive(Deserialize)]
+struct OAuthToken {
+    access_token: String,
+    refresh_token: String,
+    expires_in: f64,
+    refresh_token_expires_in: f64,
+}
+
+#[derive(Deserialize)]
+struct Uploaded {
+    video: UploadedVideo,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadedVideo {
+    pub id: u64,
+    pub uuid: String,
+    #[serde(default)]
+    pub short_uuid: String,
+}
+
+fn now() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64()
+}
+
+/// Error with the message of the PeerTube API, when the status is not successful.
+async fn check(response: Response) -> Result<Response, ProcessError> {
+    let status = response.status();
+
+    if status.is_success() || status == StatusCode::PERMANENT_REDIRECT {
+        return Ok(response);
+    }
+
+    let body = response.text().await.unwrap_or_default();
+    let detail = serde_json::from_str::<Value>(&body)
+        .ok()
+        .and_then(|v| {
+            v.get("detail")
+                .or(v.get("error"))
+                .and_then(Value::as_str)
+                .map(str::to_string)
+        })
+        .unwrap_or(body);
+
+    Err(ProcessError::Custom(format!(
+        "{NAME} answered with {status}: {}",
+        detail.trim()
+    )))
+}
+
+impl PeerTubeConfig {
+    fn api(&self, path: &str) -> String {
+        format!("{}/api/v1/{path}", self.url.trim_end_matches('/'))
+    }
+
+    async fn request_token(
+        &mut self,
+        client: &Client,
+        grant: &[(&str, &str)],
+    ) -> Result<(), ProcessError> {
+        let mut form = vec![
+            ("client_id", self.client_id.as_str()),
+            ("client_secret", self.client_secret.as_str()),
+        ];
+        form.extend_from_slice(grant);
+
+        let response = client
+            .post(self.api("users/token"))
+            .form(&form)
+            .send()
+            .await?;
+        let token: OAuthToken = check(response).await?.json().await?;
+
+        self.access_token = token.access_token;
+        self.refresh_token = token.refresh_token;
+        self.expires_in = token.expires_in;
+        self.refresh_token_expires_in = token.refresh_token_expires_in;
+        self.timestamp = now();
+
+        Ok(())
+    }
+
+    /// Get a valid access token.
+    ///
+    /// Expired tokens are refreshed, without refresh token the password is used.
+    pub async fn authorize(&mut self, client: &Client) -> Result<(), ProcessError> {
+        let now = now();
+
+        if !self.access_token.is_empty() && self.timestamp + self.expires_in - 60.0 > now {
+            return Ok(());
         }
+
+        if self.client_id.is_empty() {
+            let response = client.get(self.api("oauth-clients/local")).send().await?;
+            let oauth: OAuthClient = check(response).await?.json().await?;
+
+            self.client_id = oauth.client_id;
+            self.client_secret = oauth.client_secret;
+        }
+
+        if !self.refresh_token.is_empty()
+            && self.timestamp + self.refresh_token_expires_in - 60.0 > now
+        {
+            let refresh_token = self.refresh_token.clone();
+
+            self.request_token(
+                client,
+                &[
+                    ("grant_type", "refresh_token"),
+                    ("refresh_token", &refresh_token),
+                ],
+            )
+            .await?;
+        } else if let Some(password) = self.password.clone() {
+            let username = self.username.clone();
+
+            self.request_token(
+                client,
+                &[
+                    ("grant_type", "password"),
+                    ("response_type", "code"),
+                    ("username", &username),
+                    ("password", &password),
+                ],
+            )
+            .await?;
+        } else {
+            return Err(ProcessError::Custom(format!(
+                "{NAME} login expired, please log in again"
+            )));
+        }
+
+        Ok(())
     }
 
-    let file = tokio::fs::File::open(&source).await?;
-    let total_size = file.metadata().await.unwrap().len();
-    let mut reader_stream = ReaderStream::new(file);
-    let mut uploaded = 0;
+    fn auth(&self, request: RequestBuilder) -> RequestBuilder {
+        request.bearer_auth(&self.access_token)
+    }
+}
 
-    app.emit("upload-start", "Publish Peertube")
-        .expect("Emit Upload");
+/// PeerTube accepts up to 5 tags with 2 to 30 characters.
+fn tags(tags: &str) -> Vec<String> {
+    tags.split(',')
+        .map(str::trim)
+        .filter(|tag| (2..=30).contains(&tag.chars().count()))
+        .take(5)
+        .map(str::to_string)
+        .collect()
+}
 
-    let async_stream = async_stream::stream! {
-        while let Some(chunk) = reader_stream.next().await {
-            if let Ok(chunk) = &chunk {
-                let new = min(uploaded + (chunk.len() as u64), total_size);
-                uploaded = new;
+/// Next byte of a `308 Resume Incomplete` answer, like `Range: bytes=0-1023`.
+fn next_offset(response: &Response) -> u64 {
+    response
+        .headers()
+        .get(RANGE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.rsplit_once('-'))
+        .and_then(|(_, end)| end.parse::<u64>().ok())
+        .map_or(0, |end| end + 1)
+}
 
-                if is_running.load(Ordering::SeqCst) {
-                    warn!("Cancel upload!");
-                    break;
-                }
+/// Upload the file in parts, failed parts are resumed at the offset the server reports.
+async fn send_file(
+    upload: &Upload<'_>,
+    client: &Client,
+    peertube: &PeerTubeConfig,
+    fields: Value,
+) -> Result<UploadedVideo, ProcessError> {
+    let file = upload.file;
+    let total = fs::metadata(file).await?.len();
+    let response = peertube
+        .auth(client.post(peertube.api("videos/upload-resumable")))
+        .header("X-Upload-Content-Length", total)
+        .header("X-Upload-Content-Type", mime_type(file))
+        .json(&fields)
+        .send()
+        .await?;
+    let response = check(response).await?;
+    let location = response
+        .headers()
+        .get(LOCATION)
+        .and_then(|v| v.to_str().ok())
+        .ok_or_else(|| ProcessError::Custom(format!("{NAME} sent no upload location")))?;
+    // The location can be relative to the instance, like `//host/api/v1/...`.
+    let location = Url::parse(&peertube.url)
+        .and_then(|base| base.join(location))
+        .map_err(|e| ProcessError::Custom(format!("Invalid upload location: {e}")))?;
+
+    let mut source = File::open(file).await?;
+    let mut offset = 0;
+    let mut failures = 0;
+
+    loop {
+        if !upload.is_running.load(Ordering::SeqCst) {
+            warn!("Cancel upload!");
+
+            let _ = peertube.auth(client.delete(location.clone())).send().await;
+
+            return Err(ProcessError::Custom("Upload was cancelled".into()));
+        }
+
+        let end = (offset + CHUNK_SIZE).min(total) - 1;
+        let mut chunk = vec![0; (end + 1 - offset) as usize];
+        source.seek(SeekFrom::Start(offset)).await?;
+        source.read_exact(&mut chunk).await?;
+
+        let result = peertube
+            .auth(client.put(location.clone()))
+            .header(CONTENT_LENGTH, chunk.len())
+            .header(CONTENT_RANGE, format!("bytes {offset}-{end}/{total}"))
+            .body(chunk)
+            .send()
+            .await;
 
-                println!("{new:?}");
+        let response = match result {
+            Ok(response) if !response.status().is_server_error() => check(response).await?,
+            Ok(response) if failures < RETRIES => {
+                debug!("Upload part failed with {}", response.status());
+                failures += 1;
 
-                app.emit("upload-progress", &new).expect("Emit progress");
+                // Ask for the received bytes and continue there.
+                let response = peertube
+                    .auth(client.put(location.clone()))
+                    .header(CONTENT_LENGTH, 0)
+                    .header(CONTENT_RANGE, format!("bytes */{total}"))
+                    .send()
+                    .await?;
+                offset = next_offset(&check(response).await?);
 
-                if uploaded >= total_size {
-                    app.emit("upload-finish", "Publish Peertube").expect("Emit progress");
-                }
+                continue;
             }
-            yield chunk;
+            Err(e) if failures < RETRIES => {
+                debug!("Upload part failed: {e}");
+                failures += 1;
+                tokio::time::sleep(Duration::from_secs(2)).await;
+
+                continue;
+            }
+            Ok(response) => check(response).await?,
+            Err(e) => return Err(e.into()),
+        };
+
+        failures = 0;
+
+        if response.status() == StatusCode::PERMANENT_REDIRECT {
+            offset = next_offset(&response);
+            upload.progress(offset, total);
+
+            continue;
         }
-    };
-
-    let _ = reqwest::Client::new()
-        .post(format!("{url_peertube}/api/v1/videos/upload"))
-        .timeout(std::time::Duration::from_secs(600))
-        .header("Authorization", &format!("Bearer {token_peertube}"))
-        .multipart(
-            reqwest::multipart::Form::new()
-                .part(
-                    "videofile",
-                    reqwest::multipart::Part::stream(reqwest::Body::wrap_stream(async_stream)),
+
+        upload.progress(total, total);
+
+        return Ok(response.json::<Uploaded>().await?.video);
+    }
+}
+
+impl Publisher for PeerTubeConfig {
+    /// Upload as video of the channel, with thumbnail and the WebVTT captions of the transcript.
+    async fn publish(&mut self, upload: &Upload<'_>) -> Result<Option<String>, ProcessError> {
+        if self.url.is_empty() || self.channel_id == 0 {
+            return Err(ProcessError::Custom(format!(
+                "{NAME} needs the URL and channel ID"
+            )));
+        }
+
+        let publish = upload.publish;
+        let file = upload.file;
+        let client = Client::builder()
+            .timeout(Duration::from_secs(600))
+            .build()?;
+
+        self.authorize(&client).await?;
+
+        let mut fields = json!({
+            "channelId": self.channel_id,
+            "name": upload.name(),
+            "filename": file.file_name().unwrap_or_default().to_string_lossy(),
+            "description": publish.description,
+            "tags": tags(&publish.tags),
+        });
+
+        if let Some(privacy) = self.privacy {
+            fields["privacy"] = json!(privacy);
+        }
+
+        if let Some(category) = self.category {
+            fields["category"] = json!(category);
+        }
+
+        if let Some(language) = &self.language {
+            fields["language"] = json!(language);
+        }
+
+        let video = send_file(upload, &client, self, fields).await?;
+        let thumbnail = Path::new(&publish.thumbnail);
+
+        if !publish.thumbnail.is_empty() && thumbnail.is_file() {
+            let image = fs::read(thumbnail).await?;
+            let part = |data: Vec<u8>| {
+                Part::bytes(data).file_name(
+                    thumbnail
+                        .file_name()
+                        .unwrap_or_default()
+                        .to_string_lossy()
+                        .to_string(),
                 )
-                .text("channelId", id_peertube.to_string())
-                .text("name", publish_name)
-                .text("description", publish_description)
-                .text("name", publish_tags),
-        )
-        .send()
-        .await?;
+            };
+            let form = Form::new()
+                .part("thumbnailfile", part(image.clone()))
+                .part("previewfile", part(image));
+            let response = self
+                .auth(client.put(self.api(&format!("videos/{}", video.id))))
+                .multipart(form)
+                .send()
+                .await?;
+
+            check(response).await?;
+        }
+
+        // Auto detected languages have no code, then the video language is used.
+        let caption_lang = upload
+            .task
+            .transcript
+            .clone()
+            .filter(|lang| !["none", "auto", "ml"].contains(&lang.as_str()))
+            .or(self.language.clone());
+
+        if let (Some(vtt), Some(lang)) = (upload.captions.filter(|c| c.is_file()), caption_lang) {
+            let part = Part::bytes(fs::read(vtt).await?).file_name(
+                vtt.file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .to_string(),
+            );
+            let response = self
+                .auth(client.put(self.api(&format!("videos/{}/captions/{lang}", video.id))))
+                .multipart(Form::new().part("captionfile", part))
+                .send()
+                .await?;
+
+            check(response).await?;
+        }
+
+        let url = self.url.trim_end_matches('/');
 
-    Ok(())
+        Ok(Some(if video.short_uuid.is_empty() {
+            format!("{url}/videos/watch/{}", video.uuid)
+        } else {
+            format!("{url}/w/{}", video.short_uuid)
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filters_tags() {
+        assert_eq!(
+            tags("news, a, talk ,x,1,2,3,4,5,6"),
+            ["news", "talk"].map(String::from)
+        );
+        assert_eq!(
+            tags("one,two,three,four,five,six"),
+            ["one", "two", "three", "four", "five"].map(String::from)
+        );
+    }
 }
@@ -0,0 +1,442 @@
+use std::{
+    collections::BTreeMap, io::SeekFrom, path::Path, sync::atomic::Ordering, time::Duration,
+};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use log::*;
+use serde::{Deserialize, Serialize};
+use tauri_plugin_http::reqwest::{
+    Client, Method, RequestBuilder, Response, StatusCode, Url,
+    header::{CONTENT_LENGTH, CONTENT_TYPE, LOCATION},
+};
+use tokio::{
+    fs::{self, File},
+    io::{AsyncReadExt, AsyncSeekExt},
+};
+use ts_rs::TS;
+
+use crate::{
+    ProcessError,
+    publisher::{Publish, Publisher, Upload, mime_type},
+    utils::backup::data_path,
+};
+
+const TUS_VERSION: &str = "1.0.0";
+/// Upload URLs of unfinished uploads, by source and preset, to resume them after a restart.
+const RESUME_FILE: &str = "tus-uploads.json";
+/// Size of a `PATCH` request in MiB, without a size in the config.
+const CHUNK_SIZE: u64 = 8;
+/// Tries of one request, before the upload fails.
+const RETRIES: usize = 3;
+
+/// Server with the tus resumable upload protocol.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, TS)]
+#[ts(export, export_to = "backend.d.ts")]
+#[serde(default)]
+pub struct TusConfig {
+    /// Creation URL, like `https://example.org/files/`.
+    pub endpoint: String,
+    /// Extra headers of every request, for example `Authorization`.
+    pub headers: BTreeMap<String, String>,
+    /// `Upload-Metadata` entries, the values can contain the placeholders
+    /// `{name}`, `{description}`, `{tags}`, `{file}`, `{source}` and `{preset}`.
+    /// Without entries the file name, type and title are sent.
+    pub metadata: BTreeMap<String, String>,
+    /// Size of the upload parts in MiB.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chunk_size: Option<u64>,
+}
+
+/// Settings of tus targets in the publish fields of a task, they apply to all tus targets.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, TS)]
+#[ts(export, export_to = "backend.d.ts")]
+#[serde(default)]
+pub struct TusPublish {
+    /// Creation URL instead of the endpoint of the target, when not empty.
+    pub endpoint: String,
+    /// Headers in addition to the headers of the target, the same names replace them.
+    pub headers: BTreeMap<String, String>,
+    /// `Upload-Metadata` entries in addition to the entries of the target,
+    /// with the same placeholders.
+    pub metadata: BTreeMap<String, String>,
+}
+
+/// `Upload-Metadata` header: comma separated keys with base64 encoded values.
+fn encode_metadata(metadata: &BTreeMap<String, String>) -> String {
+    metadata
+        .iter()
+        .filter(|(key, _)| !key.is_empty() && !key.contains([' ', ',']))
+        .map(|(key, value)| {
+            if value.is_empty() {
+                key.clone()
+            } else {
+                format!("{key} {}", STANDARD.encode(value))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn upload_offset(response: &Response) -> Option<u64> {
+    response
+        .headers()
+        .get("Upload-Offset")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok())
+}
+
+async fn check(response: Response) -> Result<Response, ProcessError> {
+    let status = response.status();
+
+    if status.is_client_error() || status.is_server_error() {
+        let body = response.text().await.unwrap_or_default();
+
+        return Err(ProcessError::Custom(format!(
+            "tus server answered with {status}: {}",
+            body.trim()
+        )));
+    }
+
+    Ok(response)
+}
+
+/// Identifies the output of a source and preset, the modification time changes with every
+/// encode and can not be used. A new size starts a new upload.
+fn resume_key(endpoint: &str, upload: &Upload<'_>, size: u64) -> String {
+    format!(
+        "{endpoint}|{}|{}|{size}",
+        upload.task.path, upload.preset.name
+    )
+}
+
+/// Key without the size, older uploads of the same output share it.
+fn key_prefix(key: &str) -> &str {
+    key.rsplit_once('|').map_or(key, |(prefix, _)| prefix)
+}
+
+async fn read_resumes(path: &Path) -> BTreeMap<String, String> {
+    match fs::read_to_string(path).await {
+        Ok(content) => serde_json::from_str(&content).unwrap_or_default(),
+        Err(_) => BTreeMap::new(),
+    }
+}
+
+/// Remember or forget the upload URL of a file, a new URL replaces older uploads of the same output.
+async fn store_resume(path: &Path, key: &str, url: Option<&str>) -> Result<(), ProcessError> {
+    let mut resumes = read_resumes(path).await;
+
+    match url {
+        Some(url) => {
+            resumes.retain(|k, _| key_prefix(k) != key_prefix(key));
+            resumes.insert(key.to_string(), url.to_string());
+        }
+        None => {
+            resumes.remove(key);
+        }
+    }
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+
+    fs::write(path, serde_json::to_string_pretty(&resumes)?).await?;
+
+    Ok(())
+}
+
+impl TusConfig {
+    /// Target with the endpoint and headers of the task.
+    fn for_task(&self, publish: &Publish) -> Self {
+        let mut target = self.clone();
+
+        if let Some(tus) = &publish.tus {
+            if !tus.endpoint.trim().is_empty() {
+                target.endpoint = tus.endpoint.trim().to_string();
+            }
+
+            target.headers.extend(tus.headers.clone());
+        }
+
+        target
+    }
+
+    fn request(&self, client: &Client, method: Method, url: &str) -> RequestBuilder {
+        let mut request = client
+            .request(method, url)
+            .header("Tus-Resumable", TUS_VERSION);
+
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+
+        request
+    }
+
+    /// Entries of the target, or the file name, type and title, and the entries of the task.
+    fn metadata(&self, upload: &Upload<'_>) -> BTreeMap<String, String> {
+        let mut metadata = if self.metadata.is_empty() {
+            BTreeMap::from([
+                ("filename".to_string(), upload.fill("{file}")),
+                ("filetype".to_string(), mime_type(upload.file).to_string()),
+                ("name".to_string(), upload.name()),
+            ])
+        } else {
+            self.metadata
+                .iter()
+                .map(|(key, value)| (key.clone(), upload.fill(value)))
+                .collect()
+        };
+
+        if let Some(tus) = &upload.publish.tus {
+            for (key, value) in &tus.metadata {
+                metadata.insert(key.clone(), upload.fill(value));
+            }
+        }
+
+        metadata
+    }
+
+    /// Create the upload, returns its URL.
+    async fn create(
+        &self,
+        client: &Client,
+        upload: &Upload<'_>,
+        total: u64,
+    ) -> Result<String, ProcessError> {
+        let mut request = self
+            .request(client, Method::POST, &self.endpoint)
+            .header("Upload-Length", total)
+            .header(CONTENT_LENGTH, 0);
+        let metadata = encode_metadata(&self.metadata(upload));
+
+        if !metadata.is_empty() {
+            request = request.header("Upload-Metadata", metadata);
+        }
+
+        let response = check(request.send().await?).await?;
+        let location = response
+            .headers()
+            .get(LOCATION)
+            .and_then(|v| v.to_str().ok())
+            .ok_or_else(|| ProcessError::Custom("tus server sent no upload location".into()))?;
+
+        // The location can be relative to the endpoint.
+        Url::parse(&self.endpoint)
+            .and_then(|base| base.join(location))
+            .map(|url| url.to_string())
+            .map_err(|e| ProcessError::Custom(format!("Invalid upload location: {e}")))
+    }
+
+    /// Received bytes of an upload.
+    async fn offset(&self, client: &Client, url: &str) -> Result<u64, ProcessError> {
+        let response = self.request(client, Method::HEAD, url).send().await?;
+        let response = check(response).await?;
+
+        upload_offset(&response)
+            .ok_or_else(|| ProcessError::Custom(format!("{url} sent no upload offset")))
+    }
+
+    /// Received bytes after a failed request, failed queries use up the tries as well.
+    async fn resync(
+        &self,
+        client: &Client,
+        url: &str,
+        failures: &mut usize,
+    ) -> Result<u64, ProcessError> {
+        loop {
+            tokio::time::sleep(Duration::from_secs(2)).await;
+
+            match self.offset(client, url).await {
+                Ok(offset) => return Ok(offset),
+                Err(e) if *failures < RETRIES => {
+                    debug!("Upload offset query failed: {e}");
+                    *failures += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl Publisher for TusConfig {
+    /// Upload in parts with `PATCH` requests, unfinished uploads are continued at the offset
+    /// of the server, also after a restart.
+    async fn publish(&mut self, upload: &Upload<'_>) -> Result<Option<String>, ProcessError> {
+        let tus = tus.for_task(upload.publish);
+
+        if tus.endpoint.is_empty() {
+            return Err(ProcessError::Custom("tus target needs an endpoint".into()));
+        }
+
+        let file = upload.file;
+        let total = fs::metadata(file).await?.len();
+        let chunk_size = tus.chunk_size.unwrap_or(CHUNK_SIZE).max(1) * 1024 * 1024;
+        let client = Client::builder()
+            .timeout(Duration::from_secs(600))
+            .build()?;
+        let resumes = data_path(upload.app)?.join(RESUME_FILE);
+        let key = resume_key(&tus.endpoint, upload, total);
+        let mut offset = 0;
+        let mut location = None;
+
+        if let Some(url) = read_resumes(&resumes).await.remove(&key) {
+            match tus.offset(&client, &url).await {
+                Ok(received) => {
+                    debug!("Resume upload of {file:?} at byte {received}");
+                    offset = received;
+                    location = Some(url);
+                }
+                Err(e) => debug!("Upload {url} can not be resumed: {e}"),
+            }
+        }
+
+        let location = match location {
+            Some(url) => url,
+            None => {
+                let url = tus.create(&client, upload, total).await?;
+                store_resume(&resumes, &key, Some(&url)).await?;
+
+                url
+            }
+        };
+
+        let mut source = File::open(file).await?;
+        let mut failures = 0;
+
+        upload.progress(offset, total);
+
+        while offset < total {
+            if !upload.is_running.load(Ordering::SeqCst) {
+                // The upload URL stays, the next run continues the upload.
+                return Err(ProcessError::Custom("Upload was cancelled".into()));
+            }
+
+            let end = (offset + chunk_size).min(total);
+            let mut chunk = vec![0; (end - offset) as usize];
+            source.seek(SeekFrom::Start(offset)).await?;
+            source.read_exact(&mut chunk).await?;
+
+            let result = self
+                .request(&client, Method::PATCH, &location)
+                .header(CONTENT_TYPE, "application/offset+octet-stream")
+                .header("Upload-Offset", offset)
+                .body(chunk)
+                .send()
+                .await;
+
+            let response = match result {
+                Ok(response)
+                    if response.status().is_success() || response.status().is_client_error() =>
+                {
+                    response
+                }
+                Ok(response) if failures < RETRIES => {
+                    debug!("Upload part failed with {}", response.status());
+                    failures += 1;
+                    offset = tus.resync(&client, &location, &mut failures).await?;
+
+                    continue;
+                }
+                Err(e) if failures < RETRIES => {
+                    debug!("Upload part failed: {e}");
+                    failures += 1;
+                    offset = tus.resync(&client, &location, &mut failures).await?;
+
+                    continue;
+                }
+                Ok(response) => response,
+                Err(e) => return Err(e.into()),
+            };
+
+            // The server has other bytes than expected, continue at its offset.
+            if response.status() == StatusCode::CONFLICT && failures < RETRIES {
+                failures += 1;
+                offset = tus.resync(&client, &location, &mut failures).await?;
+
+                continue;
+            }
+
+            let response = check(response).await?;
+
+            failures = 0;
+            offset = upload_offset(&response).unwrap_or(end);
+            upload.progress(offset, total);
+        }
+
+        store_resume(&resumes, &key, None).await?;
+
+        Ok(Some(location))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_metadata() {
+        let metadata = BTreeMap::from([
+            ("filename".to_string(), "talk.mp4".to_string()),
+            ("is confidential".to_string(), "yes".to_string()),
+            ("private".to_string(), String::new()),
+        ]);
+
+        assert_eq!(encode_metadata(&metadata), "filename dGFsay5tcDQ=,private");
+    }
+
+    #[test]
+    fn applies_task_settings() {
+        let target = TusConfig {
+            endpoint: "https://example.org/files/".to_string(),
+            headers: BTreeMap::from([
+                ("Authorization".to_string(), "Bearer target".to_string()),
+                ("X-Client".to_string(), "adConverter".to_string()),
+            ]),
+            ..Default::default()
+        };
+        let publish = Publish {
+            tus: Some(TusPublish {
+                endpoint: "https://partner.example.org/uploads/".to_string(),
+                headers: BTreeMap::from([("Authorization".to_string(), "Bearer task".to_string())]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let tus = target.for_task(&publish);
+        assert_eq!(tus.endpoint, "https://partner.example.org/uploads/");
+        assert_eq!(tus.headers["Authorization"], "Bearer task");
+        assert_eq!(tus.headers["X-Client"], "adConverter");
+
+        let tus = target.for_task(&Publish::default());
+        assert_eq!(tus.endpoint, "https://example.org/files/");
+        assert_eq!(tus.headers["Authorization"], "Bearer target");
+    }
+
+    #[tokio::test]
+    async fn remembers_upload_urls() {
+        let path = std::env::temp_dir().join("adconverter-tus-test.json");
+        let _ = fs::remove_file(&path).await;
+
+        store_resume(&path, "e|a.mov|HD|10", Some("https://example.org/files/1"))
+            .await
+            .unwrap();
+        store_resume(&path, "e|b.mov|HD|10", Some("https://example.org/files/2"))
+            .await
+            .unwrap();
+        store_resume(&path, "e|b.mov|HD|12", Some("https://example.org/files/3"))
+            .await
+            .unwrap();
+        store_resume(&path, "e|a.mov|HD|10", None).await.unwrap();
+
+        assert_eq!(
+            read_resumes(&path).await,
+            BTreeMap::from([(
+                "e|b.mov|HD|12".to_string(),
+                "https://example.org/files/3".to_string()
+            )])
+        );
+
+        fs::remove_file(path).await.unwrap();
+    }
+}
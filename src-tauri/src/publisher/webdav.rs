@@ -0,0 +1,336 @@
+use std::{
+    path::Path,
+    sync::{Arc, atomic::Ordering},
+    time::Duration,
+};
+
+use log::*;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri_plugin_http::reqwest::{
+    Body, Client, Method, RequestBuilder, Response, StatusCode, Url,
+    header::{ACCEPT, CONTENT_LENGTH, CONTENT_TYPE},
+};
+use tokio::fs::{self, File};
+use ts_rs::TS;
+
+use crate::{
+    ProcessError,
+    publisher::{Publisher, Upload, file_body, mime_type},
+    utils::http_download::decode_percent,
+};
+
+/// Public link share of the Nextcloud OCS API.
+const PUBLIC_LINK: &str = "3";
+
+/// WebDAV folder, like a Nextcloud share.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, TS)]
+#[ts(export, export_to = "backend.d.ts")]
+#[serde(default)]
+pub struct WebDavConfig {
+    /// Base folder, for Nextcloud like `https://cloud.example.org/remote.php/dav/files/<user>/Deliveries`.
+    pub url: String,
+    pub username: String,
+    /// Password, for Nextcloud better an app password.
+    pub password: String,
+    /// Folder below the base folder, can contain the placeholders `{name}`, `{source}` and `{preset}`.
+    /// The subfolder of tasks with `target_subfolder` is added.
+    pub folder: String,
+    /// Create a public Nextcloud share link of the uploaded file.
+    pub share: bool,
+}
+
+/// Server root and the path in the user files of a Nextcloud WebDAV URL.
+fn nextcloud_paths(url: &Url) -> Option<(String, String)> {
+    let path = url.path();
+    let (root, rest) = path.split_once("/remote.php/")?;
+    let files = match rest.strip_prefix("dav/files/") {
+        Some(rest) => rest.split_once('/').map_or("", |(_, files)| files),
+        None => rest.strip_prefix("webdav")?,
+    };
+    let files = files
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .map(decode_percent)
+        .collect::<Vec<_>>()
+        .join("/");
+
+    Some((
+        format!("{}{root}", url.origin().ascii_serialization()),
+        format!("/{files}"),
+    ))
+}
+
+/// `getcontentlength` of a `PROPFIND` answer, the namespace prefix differs by server.
+fn content_length(xml: &str) -> Option<u64> {
+    let re = Regex::new(r"<(?:[\w-]+:)?getcontentlength[^>]*>\s*(\d+)\s*<").ok()?;
+
+    re.captures(xml)?.get(1)?.as_str().parse().ok()
+}
+
+/// Append folders or a file name to a URL, the segments are encoded.
+fn join(url: &Url, segments: &[String]) -> Result<Url, ProcessError> {
+    let mut url = url.clone();
+
+    url.path_segments_mut()
+        .map_err(|_| ProcessError::Custom(format!("{url} can not have a path")))?
+        .pop_if_empty()
+        .extend(segments);
+
+    Ok(url)
+}
+
+async fn check(response: Response) -> Result<Response, ProcessError> {
+    let status = response.status();
+
+    if status.is_client_error() || status.is_server_error() {
+        return Err(ProcessError::Custom(format!(
+            "WebDAV request to {} failed with {status}",
+            response.url()
+        )));
+    }
+
+    Ok(response)
+}
+
+impl WebDavConfig {
+    fn request(&self, client: &Client, method: Method, url: Url) -> RequestBuilder {
+        client
+            .request(method, url)
+            .basic_auth(&self.username, Some(&self.password))
+    }
+
+    /// Remote folders of the upload.
+    fn folders(&self, upload: &Upload<'_>) -> Vec<String> {
+        let mut folders: Vec<String> = upload
+            .fill(&self.folder)
+            .split(['/', '\\'])
+            .map(str::trim)
+            .filter(|s| !s.is_empty() && *s != "." && *s != "..")
+            .map(str::to_string)
+            .collect();
+
+        // Like the local output, which is in a folder named after the source folder.
+        if upload.task.target_subfolder
+            && let Some(parent) = Path::new(&upload.task.path)
+                .parent()
+                .and_then(Path::file_name)
+        {
+            folders.push(parent.to_string_lossy().to_string());
+        }
+
+        folders
+    }
+
+    /// Create the folders one after another, existing folders answer with `405`.
+    async fn create_folders(
+        &self,
+        client: &Client,
+        base: &Url,
+        folders: &[String],
+    ) -> Result<Url, ProcessError> {
+        for i in 1..=folders.len() {
+            let url = join(base, &folders[..i])?;
+            let response = self
+                .request(client, Method::from_bytes(b"MKCOL").unwrap(), url)
+                .send()
+                .await?;
+
+            if response.status() != StatusCode::METHOD_NOT_ALLOWED {
+                check(response).await?;
+            }
+        }
+
+        join(base, folders)
+    }
+
+    /// Upload one file to `url`, its size is compared afterwards.
+    async fn put(
+        &self,
+        client: &Client,
+        upload: &Upload<'_>,
+        file: &Path,
+        url: Url,
+        progress: impl Fn(u64) + Send + 'static,
+    ) -> Result<Url, ProcessError> {
+        let size = fs::metadata(file).await?.len();
+        let body = file_body(File::open(file).await?, upload.is_running.clone(), progress);
+        let response = self
+            .request(client, Method::PUT, url.clone())
+            .header(CONTENT_TYPE, mime_type(file))
+            .header(CONTENT_LENGTH, size)
+            .body(Body::wrap_stream(body))
+            .send()
+            .await;
+
+        if !upload.is_running.load(Ordering::SeqCst) {
+            return Err(ProcessError::Custom("Upload was cancelled".into()));
+        }
+
+        check(response?).await?;
+
+        let response = self
+            .request(client, Method::from_bytes(b"PROPFIND").unwrap(), url.clone())
+            .header("Depth", "0")
+            .header(CONTENT_TYPE, "application/xml")
+            .body(
+                r#"<?xml version="1.0"?><d:propfind xmlns:d="DAV:"><d:prop><d:getcontentlength/></d:prop></d:propfind>"#,
+            )
+            .send()
+            .await?;
+        let remote = content_length(&check(response).await?.text().await?);
+
+        if remote != Some(size) {
+            return Err(ProcessError::Custom(format!(
+                "{url} has {} bytes instead of {size}",
+                remote.map_or("unknown".to_string(), |r| r.to_string())
+            )));
+        }
+
+        Ok(url)
+    }
+
+    /// Public link of a file with the Nextcloud OCS API.
+    async fn share(&self, client: &Client, url: &Url) -> Result<String, ProcessError> {
+        let (server, path) = nextcloud_paths(url).ok_or_else(|| {
+            ProcessError::Custom(format!("{url} is no Nextcloud WebDAV URL, can not share"))
+        })?;
+        let response = self
+            .request(
+                client,
+                Method::POST,
+                Url::parse(&format!(
+                    "{server}/ocs/v2.php/apps/files_sharing/api/v1/shares"
+                ))
+                .map_err(|e| ProcessError::Custom(e.to_string()))?,
+            )
+            .header("OCS-APIRequest", "true")
+            .header(ACCEPT, "application/json")
+            .form(&[("path", path.as_str()), ("shareType", PUBLIC_LINK)])
+            .send()
+            .await?;
+        let value: Value = check(response).await?.json().await?;
+
+        value["ocs"]["data"]["url"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| {
+                ProcessError::Custom(format!(
+                    "Nextcloud sent no share link: {}",
+                    value["ocs"]["meta"]["message"]
+                ))
+            })
+    }
+}
+
+impl Publisher for WebDavConfig {
+    /// Upload the output and the WebVTT captions, returns the share link or the file URL.
+    async fn publish(&mut self, upload: &Upload<'_>) -> Result<Option<String>, ProcessError> {
+        let base = Url::parse(&self.url)
+            .map_err(|e| ProcessError::Custom(format!("Invalid WebDAV URL: {e}")))?;
+        let client = Client::builder()
+            .timeout(Duration::from_secs(3600))
+            .build()?;
+        let folder = self
+            .create_folders(&client, &base, &self.folders(upload))
+            .await?;
+        let name = |path: &Path| {
+            path.file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string()
+        };
+        let mut files = vec![(upload.file, name(upload.file))];
+
+        // Named like the output, so players find the captions.
+        if let Some(captions) = upload.captions.filter(|c| c.is_file()) {
+            files.push((captions, name(&upload.file.with_extension("vtt"))));
+        }
+
+        let mut sizes = vec![];
+
+        for (file, _) in &files {
+            sizes.push(fs::metadata(file).await?.len());
+        }
+
+        let total: u64 = sizes.iter().sum();
+        let progress = Arc::new(upload.progress_fn());
+        let mut done = 0;
+        let mut urls = vec![];
+
+        for ((file, name), size) in files.into_iter().zip(sizes) {
+            let url = join(&folder, &[name])?;
+            let progress = progress.clone();
+            let url = self
+                .put(&client, upload, file, url, move |sent| {
+                    progress(done + sent, total)
+                })
+                .await?;
+
+            debug!("Uploaded {file:?} to {url}");
+            done += size;
+            urls.push(url);
+        }
+
+        let url = urls.swap_remove(0);
+
+        if self.share {
+            return Ok(Some(self.share(&client, &url).await?));
+        }
+
+        Ok(Some(url.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_nextcloud_paths() {
+        let url = Url::parse(
+            "https://example.org/cloud/remote.php/dav/files/anna/Deliveries/My%20Talk.mp4",
+        )
+        .unwrap();
+
+        assert_eq!(
+            nextcloud_paths(&url),
+            Some((
+                "https://example.org/cloud".to_string(),
+                "/Deliveries/My Talk.mp4".to_string()
+            ))
+        );
+        assert_eq!(
+            nextcloud_paths(&Url::parse("https://example.org/remote.php/webdav/a.mp4").unwrap()),
+            Some(("https://example.org".to_string(), "/a.mp4".to_string()))
+        );
+        assert_eq!(
+            nextcloud_paths(&Url::parse("https://dav.example.org/files/a.mp4").unwrap()),
+            None
+        );
+    }
+
+    #[test]
+    fn reads_content_length() {
+        let xml = r#"<?xml version="1.0"?>
+            <d:multistatus xmlns:d="DAV:"><d:response><d:propstat><d:prop>
+            <d:getcontentlength>1048576</d:getcontentlength>
+            </d:prop></d:propstat></d:response></d:multistatus>"#;
+
+        assert_eq!(content_length(xml), Some(1048576));
+        assert_eq!(content_length("<D:getcontentlength/>"), None);
+    }
+
+    #[test]
+    fn joins_encoded_segments() {
+        let base = Url::parse("https://example.org/dav/").unwrap();
+
+        assert_eq!(
+            join(&base, &["A B".to_string(), "c#1.mp4".to_string()])
+                .unwrap()
+                .as_str(),
+            "https://example.org/dav/A%20B/c%231.mp4"
+        );
+    }
+}
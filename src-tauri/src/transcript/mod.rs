@@ -1,6 +1,6 @@
 use std::{
     env,
-    path::Path,
+    path::{Path, PathBuf},
     process::Stdio,
     sync::{
         Arc,
@@ -17,7 +17,7 @@ use tokio::{
     sync::Mutex,
 };
 
-mod process;
+pub mod process;
 
 use crate::{
     Config, ProcessError, Task,
@@ -38,7 +38,7 @@ pub async fn run(
     source: &Path,
     task: &Task,
     progress_bar: Option<ProgressBar>,
-) -> Result<(), ProcessError> {
+) -> Result<Option<PathBuf>, ProcessError> {
     let progress_clone = progress_bar.clone();
     let app_clone = app.clone();
     let running_clone = is_running.clone();
@@ -181,17 +181,20 @@ pub async fn run(
 
     *child.lock().await = None;
 
+    let mut vtt = None;
+
     if temp_out.is_file() {
         let duration = (task.probe.clone().format_duration() * 1000.0) as u64;
 
         optimize_vtt(&temp_out, &output_path, duration, lang).await?;
 
         fs::remove_file(temp_out).await?;
+        vtt = Some(output_path);
     }
 
     if let Some(a) = &app {
         a.emit("transcript-finish", lang).expect("Emit progress");
     }
 
-    Ok(())
+    Ok(vtt)
 }
@@ -222,7 +222,7 @@ fn process_vtt(subtitles: Vec<Subtitle>, duration: u64, lang: &str) -> Vec<Subti
     }
 
     if let Some(mut last) = prev {
-        if last.end > duration {
+        if duration > 0 && last.end > duration {
             let new_end = (duration - 300).max(last.start + 500); // min. 500ms length
             last.end = new_end;
         }
@@ -0,0 +1,531 @@
+use std::{
+    env,
+    io::{Cursor, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use log::*;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tauri::{AppHandle, Manager};
+use tokio::fs;
+use ts_rs::TS;
+use zip::write::SimpleFileOptions;
+
+use crate::{
+    ProcessError,
+    cli::IDENTIFIER,
+    utils::{
+        presets::preset_path,
+        schema::{Schema, validate},
+        template::template_path,
+    },
+};
+
+const MANIFEST: &str = "manifest.json";
+const CONFIG: &str = "config.json";
+const BACKUP_VERSION: u32 = 1;
+/// Passwords, keys and tokens of publish targets, only exported on request.
+const SECRET_FIELDS: [&str; 5] = [
+    "password",
+    "client_secret",
+    "access_token",
+    "refresh_token",
+    "secret_key",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize, TS)]
+#[ts(export, export_to = "backend.d.ts")]
+#[serde(rename_all = "lowercase")]
+pub enum BackupKind {
+    /// One key of the config store.
+    Config,
+    Preset,
+    /// Named template.
+    Template,
+    /// Transcript scripts.
+    Script,
+}
+
+impl BackupKind {
+    const FILES: [Self; 3] = [Self::Preset, Self::Template, Self::Script];
+
+    fn folder(self) -> &'static str {
+        match self {
+            Self::Config => "",
+            Self::Preset => "presets",
+            Self::Template => "templates",
+            Self::Script => "scripts",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize, TS)]
+#[ts(export, export_to = "backend.d.ts")]
+#[serde(rename_all = "lowercase")]
+pub enum EntryStatus {
+    New,
+    Unchanged,
+    /// Exists with different content.
+    Conflict,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, TS)]
+#[ts(export, export_to = "backend.d.ts")]
+pub struct BackupEntry {
+    pub kind: BackupKind,
+    pub name: String,
+    pub status: EntryStatus,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, TS)]
+#[ts(export, export_to = "backend.d.ts")]
+pub struct BackupManifest {
+    pub version: u32,
+    pub app_version: String,
+    pub created: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, TS)]
+#[ts(export, export_to = "backend.d.ts")]
+pub struct BackupPreview {
+    pub manifest: BackupManifest,
+    pub entries: Vec<BackupEntry>,
+}
+
+/// Settings bundle: the config store, user presets, named templates and transcript scripts.
+///
+/// Templates next to the media files are not included, only the named templates of the application data.
+#[derive(Clone, Debug, Default)]
+pub struct Backup {
+    pub manifest: BackupManifest,
+    pub config: Map<String, Value>,
+    files: Vec<(BackupKind, String, Vec<u8>)>,
+}
+
+/// Application data folder, the same for the GUI and the CLI.
+pub fn data_path(app: &Option<AppHandle>) -> Result<PathBuf, ProcessError> {
+    let path = match app {
+        Some(a) => a.path().app_data_dir()?,
+        None => dirs::data_dir()
+            .unwrap_or(env::current_dir()?)
+            .join(IDENTIFIER),
+    };
+
+    Ok(path)
+}
+
+fn folder(app: &Option<AppHandle>, kind: BackupKind) -> Result<PathBuf, ProcessError> {
+    match kind {
+        BackupKind::Preset => Ok(preset_path(app)?),
+        BackupKind::Template => template_path(app),
+        _ => Ok(data_path(app)?.join(kind.folder())),
+    }
+}
+
+async fn read_folder(path: &Path) -> Result<Vec<(String, Vec<u8>)>, ProcessError> {
+    let mut files = vec![];
+
+    if !path.is_dir() {
+        return Ok(files);
+    }
+
+    let mut entries = fs::read_dir(path).await?;
+
+    while let Some(entry) = entries.next_entry().await? {
+        if entry.path().is_file() {
+            let name = entry.file_name().to_string_lossy().to_string();
+
+            files.push((name, fs::read(entry.path()).await?));
+        }
+    }
+
+    files.sort_by(|a, b| a.0.cmp(&b.0));
+
+    Ok(files)
+}
+
+fn is_secret_header(name: &str) -> bool {
+    name.eq_ignore_ascii_case("authorization")
+}
+
+/// Remove the credentials of the publish targets, also `Authorization` headers.
+fn strip_secrets(config: &mut Map<String, Value>) {
+    let Some(Value::Object(targets)) = config.get_mut("publish_targets") else {
+        return;
+    };
+
+    for target in targets.values_mut().filter_map(Value::as_object_mut) {
+        for field in SECRET_FIELDS {
+            target.remove(field);
+        }
+
+        if let Some(Value::Object(headers)) = target.get_mut("headers") {
+            headers.retain(|name, _| !is_secret_header(name));
+        }
+    }
+}
+
+/// Publish targets of a backup without credentials keep the credentials of the current targets.
+fn keep_secrets(incoming: &mut Value, current: Option<&Value>) {
+    let (Some(targets), Some(Value::Object(current))) = (incoming.as_object_mut(), current) else {
+        return;
+    };
+
+    for (name, target) in targets {
+        let (Some(target), Some(Value::Object(stored))) =
+            (target.as_object_mut(), current.get(name))
+        else {
+            continue;
+        };
+
+        for field in SECRET_FIELDS {
+            if !target.contains_key(field)
+                && let Some(value) = stored.get(field)
+            {
+                target.insert(field.to_string(), value.clone());
+            }
+        }
+
+        if let (Some(Value::Object(headers)), Some(Value::Object(stored))) =
+            (target.get_mut("headers"), stored.get("headers"))
+        {
+            for (header, value) in stored.iter().filter(|(h, _)| is_secret_header(h)) {
+                if !headers.keys().any(|h| is_secret_header(h)) {
+                    headers.insert(header.clone(), value.clone());
+                }
+            }
+        }
+    }
+}
+
+fn status<T: PartialEq>(current: Option<T>, incoming: T) -> EntryStatus {
+    match current {
+        None => EntryStatus::New,
+        Some(c) if c == incoming => EntryStatus::Unchanged,
+        Some(_) => EntryStatus::Conflict,
+    }
+}
+
+impl Backup {
+    /// Collect the current settings, `config` are the values of the config store.
+    ///
+    /// Credentials of the publish targets are only included with `secrets`.
+    pub async fn collect(
+        app: &Option<AppHandle>,
+        mut config: Map<String, Value>,
+        secrets: bool,
+    ) -> Result<Self, ProcessError> {
+        let mut files = vec![];
+
+        if !secrets {
+            strip_secrets(&mut config);
+        }
+
+        for kind in BackupKind::FILES {
+            for (name, data) in read_folder(&folder(app, kind)?).await? {
+                files.push((kind, name, data));
+            }
+        }
+
+        Ok(Self {
+            manifest: BackupManifest {
+                version: BACKUP_VERSION,
+                app_version: env!("CARGO_PKG_VERSION").to_string(),
+                created: chrono::Local::now().to_rfc3339(),
+            },
+            config,
+            files,
+        })
+    }
+
+    pub fn to_zip(&self) -> Result<Vec<u8>, ProcessError> {
+        let mut archive = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = SimpleFileOptions::default();
+
+        archive.start_file(MANIFEST, options)?;
+        archive.write_all(serde_json::to_string_pretty(&self.manifest)?.as_bytes())?;
+
+        archive.start_file(CONFIG, options)?;
+        archive.write_all(serde_json::to_string_pretty(&self.config)?.as_bytes())?;
+
+        for (kind, name, data) in &self.files {
+            archive.start_file(format!("{}/{name}", kind.folder()), options)?;
+            archive.write_all(data)?;
+        }
+
+        Ok(archive.finish()?.into_inner())
+    }
+
+    pub fn from_zip(bytes: Vec<u8>) -> Result<Self, ProcessError> {
+        let mut archive = zip::ZipArchive::new(Cursor::new(bytes))?;
+        let mut backup = Self::default();
+        let mut has_manifest = false;
+
+        for i in 0..archive.len() {
+            let mut file = archive.by_index(i)?;
+            let Some(path) = file.enclosed_name() else {
+                continue;
+            };
+
+            if !file.is_file() {
+                continue;
+            }
+
+            let mut data = vec![];
+            file.read_to_end(&mut data)?;
+
+            let name = path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string();
+            let parent = path
+                .parent()
+                .map(|p| p.to_string_lossy().to_string())
+                .unwrap_or_default();
+
+            match (parent.as_str(), name.as_str()) {
+                ("", MANIFEST) => {
+                    backup.manifest = serde_json::from_slice(&data)?;
+                    has_manifest = true;
+                }
+                ("", CONFIG) => backup.config = serde_json::from_slice(&data)?,
+                _ => match BackupKind::FILES.iter().find(|k| k.folder() == parent) {
+                    Some(kind) => {
+                        match kind {
+                            BackupKind::Preset => {
+                                validate(Schema::Preset, &path, &String::from_utf8_lossy(&data))?;
+                            }
+                            BackupKind::Template => {
+                                validate(Schema::Template, &path, &String::from_utf8_lossy(&data))?;
+                            }
+                            _ => {}
+                        }
+
+                        backup.files.push((*kind, name, data));
+                    }
+                    None => warn!("Skip unknown backup file {path:?}"),
+                },
+            }
+        }
+
+        if !has_manifest {
+            return Err(ProcessError::Custom(
+                "Not a settings backup, manifest.json is missing".to_string(),
+            ));
+        }
+
+        if backup.manifest.version > BACKUP_VERSION {
+            return Err(ProcessError::Custom(format!(
+                "Backup version {} is newer than the supported version {BACKUP_VERSION}, update adConverter",
+                backup.manifest.version
+            )));
+        }
+
+        Ok(backup)
+    }
+
+    pub async fn write(&self, target: &Path) -> Result<(), ProcessError> {
+        fs::write(target, self.to_zip()?).await?;
+
+        Ok(())
+    }
+
+    pub async fn read(source: &Path) -> Result<Self, ProcessError> {
+        Self::from_zip(fs::read(source).await?)
+    }
+
+    /// Compare the backup with the current settings.
+    pub async fn preview(
+        &self,
+        app: &Option<AppHandle>,
+        config: &Map<String, Value>,
+    ) -> Result<BackupPreview, ProcessError> {
+        let mut entries = vec![];
+
+        for (key, value) in &self.config {
+            entries.push(BackupEntry {
+                kind: BackupKind::Config,
+                name: key.clone(),
+                status: status(config.get(key), &self.config_value(key, value, config)),
+            });
+        }
+
+        for (kind, name, data) in &self.files {
+            let current = fs::read(folder(app, *kind)?.join(name)).await.ok();
+
+            entries.push(BackupEntry {
+                kind: *kind,
+                name: name.clone(),
+                status: status(current.as_ref(), data),
+            });
+        }
+
+        Ok(BackupPreview {
+            manifest: self.manifest.clone(),
+            entries,
+        })
+    }
+
+    /// Config value of the backup, completed with the current credentials.
+    fn config_value(&self, key: &str, value: &Value, config: &Map<String, Value>) -> Value {
+        let mut value = value.clone();
+
+        if key == "publish_targets" {
+            keep_secrets(&mut value, config.get(key));
+        }
+
+        value
+    }
+
+    /// Merge the backup config into `config`, conflicting keys are only replaced with `overwrite`.
+    pub fn merge_config(
+        &self,
+        config: &mut Map<String, Value>,
+        overwrite: bool,
+    ) -> Vec<BackupEntry> {
+        let mut merged = vec![];
+
+        for (key, value) in &self.config {
+            let value = self.config_value(key, value, config);
+            let status = status(config.get(key), &value);
+
+            if status == EntryStatus::New || (status == EntryStatus::Conflict && overwrite) {
+                config.insert(key.clone(), value);
+
+                merged.push(BackupEntry {
+                    kind: BackupKind::Config,
+                    name: key.clone(),
+                    status,
+                });
+            }
+        }
+
+        merged
+    }
+
+    /// Write presets, templates and scripts, conflicting files are only replaced with `overwrite`.
+    pub async fn restore_files(
+        &self,
+        app: &Option<AppHandle>,
+        overwrite: bool,
+    ) -> Result<Vec<BackupEntry>, ProcessError> {
+        let mut restored = vec![];
+
+        for (kind, name, data) in &self.files {
+            let directory = folder(app, *kind)?;
+            let path = directory.join(name);
+            let current = fs::read(&path).await.ok();
+
+            let status = status(current.as_ref(), data);
+
+            if status == EntryStatus::Unchanged || (status == EntryStatus::Conflict && !overwrite) {
+                continue;
+            }
+
+            fs::create_dir_all(&directory).await?;
+            fs::write(&path, data).await?;
+
+            restored.push(BackupEntry {
+                kind: *kind,
+                name: name.clone(),
+                status,
+            });
+        }
+
+        Ok(restored)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn backup() -> Backup {
+        Backup {
+            manifest: BackupManifest {
+                version: BACKUP_VERSION,
+                ..Default::default()
+            },
+            config: json!({ "copyright": "ADtv", "lufs": { "i": -16.0, "lra": 9.0, "tp": -1.0 } })
+                .as_object()
+                .cloned()
+                .unwrap(),
+            files: vec![(
+                BackupKind::Script,
+                "transcript_cli.py".to_string(),
+                b"print()".to_vec(),
+            )],
+        }
+    }
+
+    #[test]
+    fn zip_round_trip() {
+        let backup = backup();
+        let restored = Backup::from_zip(backup.to_zip().unwrap()).unwrap();
+
+        assert_eq!(restored.config, backup.config);
+        assert_eq!(restored.files, backup.files);
+    }
+
+    #[test]
+    fn merge_keeps_conflicts() {
+        let backup = backup();
+        let mut config = json!({ "copyright": "Local" })
+            .as_object()
+            .cloned()
+            .unwrap();
+
+        backup.merge_config(&mut config, false);
+        assert_eq!(config["copyright"], "Local");
+        assert_eq!(config["lufs"]["i"], -16.0);
+
+        backup.merge_config(&mut config, true);
+        assert_eq!(config["copyright"], "ADtv");
+    }
+
+    #[test]
+    fn secrets_are_stripped_and_kept() {
+        let targets = json!({
+            "Cloud": {
+                "type": "webdav",
+                "url": "https://cloud.example.org/dav",
+                "username": "anna",
+                "password": "secret"
+            },
+            "Tus": {
+                "type": "tus",
+                "endpoint": "https://example.org/files/",
+                "headers": { "Authorization": "Bearer token", "X-Project": "talks" }
+            }
+        });
+        let mut config = json!({ "publish_targets": targets })
+            .as_object()
+            .cloned()
+            .unwrap();
+
+        strip_secrets(&mut config);
+
+        assert_eq!(config["publish_targets"]["Cloud"].get("password"), None);
+        assert_eq!(
+            config["publish_targets"]["Tus"]["headers"],
+            json!({ "X-Project": "talks" })
+        );
+
+        let backup = Backup {
+            config: config.clone(),
+            ..Default::default()
+        };
+        let mut current = json!({ "publish_targets": targets })
+            .as_object()
+            .cloned()
+            .unwrap();
+
+        // Only the credentials are missing, so nothing changes.
+        assert!(backup.merge_config(&mut current, true).is_empty());
+        assert_eq!(current["publish_targets"], targets);
+    }
+}
@@ -0,0 +1,628 @@
+use std::{
+    collections::BTreeMap,
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+};
+
+use log::*;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{Map, Value, json};
+use tauri::AppHandle;
+use tokio::fs;
+use ts_rs::TS;
+
+use crate::{ProcessError, publisher::PublishTarget, utils::backup::data_path};
+
+/// Version of the config format, older configs are migrated on load.
+pub const CONFIG_VERSION: u32 = 2;
+pub const DEFAULT_DOWNLOAD_ARGS: &str = "--output \"%(title)s.%(ext)s\"";
+
+/// Prefix of environment variables, which override single config values.
+pub const ENV_PREFIX: &str = "ADCONVERTER_";
+
+const LUFS_I: RangeInclusive<f64> = -70.0..=-5.0;
+const LUFS_LRA: RangeInclusive<f64> = 1.0..=50.0;
+const LUFS_TP: RangeInclusive<f64> = -9.0..=0.0;
+
+/// Fields of older versions, migrated to other fields.
+pub const REMOVED_FIELDS: [&str; 1] = ["publisher"];
+
+const PATH_FIELDS: [&str; 4] = [
+    "ffmpeg_path",
+    "download_path",
+    "yt_dlp_path",
+    "shared_preset_path",
+];
+
+#[derive(Debug, Clone, Deserialize, Serialize, TS)]
+#[ts(export, export_to = "backend.d.ts")]
+#[serde(default)]
+pub struct Config {
+    pub version: u32,
+    pub copyright: String,
+    #[serde(deserialize_with = "empty_path")]
+    pub ffmpeg_path: Option<PathBuf>,
+    #[serde(deserialize_with = "empty_path")]
+    pub download_path: Option<PathBuf>,
+    pub download_args: String,
+    #[serde(deserialize_with = "empty_path")]
+    pub yt_dlp_path: Option<PathBuf>,
+    /// Shared preset folder, for example on a network drive.
+    #[serde(deserialize_with = "empty_path")]
+    pub shared_preset_path: Option<PathBuf>,
+    pub lufs: LufsConfig,
+    pub transcript_cmd: String,
+    pub transcript_lang: Vec<LangConfig>,
+    /// Preset, which is published to all targets, when presets have no own targets.
+    pub publish_preset: Option<String>,
+    /// Named delivery targets for published presets.
+    pub publish_targets: BTreeMap<String, PublishTarget>,
+    /// Presets selected for new tasks.
+    pub default_presets: Vec<String>,
+    /// Active profile.
+    pub profile: Option<String>,
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+/// Named set of values, which replace the base values while the profile is active.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, TS)]
+#[ts(export, export_to = "backend.d.ts")]
+#[serde(default)]
+pub struct Profile {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub copyright: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lufs: Option<LufsConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transcript_cmd: Option<String>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "empty_path"
+    )]
+    pub download_path: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub download_args: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_presets: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize, TS)]
+#[ts(export, export_to = "backend.d.ts")]
+pub struct LangConfig {
+    pub name: String,
+    pub code: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, TS)]
+#[ts(export, export_to = "backend.d.ts")]
+pub struct LufsConfig {
+    pub i: f64,
+    pub lra: f64,
+    pub tp: f64,
+}
+
+impl Default for LufsConfig {
+    fn default() -> Self {
+        Self {
+            i: -17.0,
+            lra: 9.0,
+            tp: -1.0,
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        let lang = |name: &str, code: &str| LangConfig {
+            name: name.to_string(),
+            code: code.to_string(),
+        };
+
+        Self {
+            version: CONFIG_VERSION,
+            copyright: String::new(),
+            ffmpeg_path: None,
+            download_path: None,
+            download_args: DEFAULT_DOWNLOAD_ARGS.to_string(),
+            yt_dlp_path: None,
+            shared_preset_path: None,
+            lufs: LufsConfig::default(),
+            transcript_cmd: String::new(),
+            transcript_lang: vec![
+                lang("None", "none"),
+                lang("Auto", "auto"),
+                lang("Multilingual", "ml"),
+                lang("German", "de"),
+                lang("English", "en"),
+                lang("Spanish", "es"),
+            ],
+            publish_preset: None,
+            publish_targets: BTreeMap::new(),
+            default_presets: vec![],
+            profile: None,
+            profiles: BTreeMap::new(),
+        }
+    }
+}
+
+pub fn non_empty_path(path: &str) -> Option<PathBuf> {
+    (!path.trim().is_empty()).then(|| PathBuf::from(path))
+}
+
+/// The GUI stores unset paths as empty strings.
+fn empty_path<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<PathBuf>, D::Error> {
+    let path: Option<String> = Option::deserialize(deserializer)?;
+
+    Ok(path.as_deref().and_then(non_empty_path))
+}
+
+/// Config file of the CLI, the GUI uses the same file through the tauri store.
+pub fn config_path(app: &Option<AppHandle>) -> Result<PathBuf, ProcessError> {
+    Ok(data_path(app)?.join("config.json"))
+}
+
+/// Migrate a raw config to the current version, returns `true` if something changed.
+fn migrate(value: &mut Value) -> bool {
+    let Value::Object(map) = value else {
+        return false;
+    };
+
+    let version = map.get("version").and_then(Value::as_u64).unwrap_or(0);
+
+    if version >= u64::from(CONFIG_VERSION) {
+        return false;
+    }
+
+    // Version 0: the GUI stored unset paths and the publish preset as empty strings.
+    if version < 1 {
+        for key in PATH_FIELDS.iter().chain(&["publish_preset"]) {
+            if map
+                .get(*key)
+                .and_then(Value::as_str)
+                .is_some_and(|p| non_empty_path(p).is_none())
+            {
+                map.remove(*key);
+            }
+        }
+    }
+
+    // Version 1: one untyped publisher object, keyed by platform.
+    if version < 2
+        && let Some(Value::Object(publisher)) = map.remove(REMOVED_FIELDS[0])
+    {
+        let mut targets = Map::new();
+
+        if let Some(Value::Object(mut peertube)) = publisher.get("peertube").cloned() {
+            peertube.insert("type".to_string(), json!("peertube"));
+            targets.insert("PeerTube".to_string(), Value::Object(peertube));
+        }
+
+        map.insert("publish_targets".to_string(), Value::Object(targets));
+    }
+
+    map.insert("version".to_string(), json!(CONFIG_VERSION));
+
+    true
+}
+
+impl Config {
+    /// Read a raw config, missing fields get their defaults.
+    /// Returns the config and whether it was migrated and should be saved.
+    pub fn from_value(mut value: Value) -> Result<(Self, bool), ProcessError> {
+        if value.is_null() {
+            value = json!({});
+        }
+
+        let migrated = migrate(&mut value);
+        let config: Self = serde_path_to_error::deserialize(value).map_err(|e| {
+            ProcessError::Custom(format!(
+                "Invalid config value \"{}\": {}",
+                e.path(),
+                e.inner()
+            ))
+        })?;
+
+        if config.version > CONFIG_VERSION {
+            return Err(ProcessError::Custom(format!(
+                "Config version {} is newer than the supported version {CONFIG_VERSION}",
+                config.version
+            )));
+        }
+
+        Ok((config, migrated))
+    }
+
+    pub fn to_map(&self) -> Result<Map<String, Value>, ProcessError> {
+        match serde_json::to_value(self)? {
+            Value::Object(map) => Ok(map),
+            _ => Ok(Map::new()),
+        }
+    }
+
+    /// The config with the values of profile `name`.
+    pub fn with_profile(&self, name: &str) -> Result<Self, ProcessError> {
+        let profile = self.profiles.get(name).ok_or_else(|| {
+            ProcessError::Custom(format!(
+                "Unknown profile \"{name}\", available: {}",
+                self.profiles.keys().cloned().collect::<Vec<_>>().join(", ")
+            ))
+        })?;
+        let mut config = self.clone();
+
+        if let Some(copyright) = &profile.copyright {
+            config.copyright.clone_from(copyright);
+        }
+        if let Some(lufs) = &profile.lufs {
+            config.lufs = lufs.clone();
+        }
+        if let Some(transcript_cmd) = &profile.transcript_cmd {
+            config.transcript_cmd.clone_from(transcript_cmd);
+        }
+        if profile.download_path.is_some() {
+            config.download_path.clone_from(&profile.download_path);
+        }
+        if let Some(download_args) = &profile.download_args {
+            config.download_args.clone_from(download_args);
+        }
+        if let Some(default_presets) = &profile.default_presets {
+            config.default_presets.clone_from(default_presets);
+        }
+
+        config.profile = Some(name.to_string());
+
+        Ok(config)
+    }
+
+    /// The config with the values of the active profile.
+    pub fn active(&self) -> Self {
+        match &self.profile {
+            Some(name) => self.with_profile(name).unwrap_or_else(|e| {
+                warn!("{e}");
+
+                let mut config = self.clone();
+                config.profile = None;
+                config
+            }),
+            None => self.clone(),
+        }
+    }
+
+    fn errors(&self) -> Vec<String> {
+        let mut errors = vec![];
+
+        if let Some(path) = &self.ffmpeg_path
+            && !path.exists()
+        {
+            errors.push(format!("ffmpeg_path: {path:?} does not exist"));
+        }
+
+        // A bare command name like `yt-dlp` is found in PATH.
+        if let Some(path) = &self.yt_dlp_path
+            && path.components().count() > 1
+            && !path.exists()
+        {
+            errors.push(format!("yt_dlp_path: {path:?} does not exist"));
+        }
+
+        for (field, value, range) in [
+            ("lufs.i", self.lufs.i, LUFS_I),
+            ("lufs.lra", self.lufs.lra, LUFS_LRA),
+            ("lufs.tp", self.lufs.tp, LUFS_TP),
+        ] {
+            if !range.contains(&value) {
+                errors.push(format!(
+                    "{field}: {value} is outside of {:.1} to {:.1}",
+                    range.start(),
+                    range.end()
+                ));
+            }
+        }
+
+        if !self.transcript_cmd.trim().is_empty() {
+            for placeholder in ["%file%", "%lang%"] {
+                if !self.transcript_cmd.contains(placeholder) {
+                    errors.push(format!("transcript_cmd: {placeholder} is missing"));
+                }
+            }
+        }
+
+        errors
+    }
+
+    /// Check values which deserialize fine but can't work, in the base config and the active profile.
+    ///
+    /// Errors of the other profiles are only logged, they fail when a task selects the profile.
+    pub fn validate(&self) -> Result<(), ProcessError> {
+        let mut errors = self.errors();
+
+        if let Some(name) = &self.profile
+            && !self.profiles.contains_key(name)
+        {
+            errors.push(format!("profile: \"{name}\" does not exist"));
+        }
+
+        for name in self.profiles.keys() {
+            let base = errors.clone();
+
+            for error in self
+                .with_profile(name)
+                .map(|c| c.errors())
+                .unwrap_or_default()
+            {
+                if base.contains(&error) {
+                    continue;
+                }
+
+                if self.profile.as_ref() == Some(name) {
+                    errors.push(format!("profiles.{name}.{error}"));
+                } else {
+                    warn!("Invalid config: profiles.{name}.{error}");
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ProcessError::Custom(format!(
+                "Invalid config: {}",
+                errors.join(", ")
+            )))
+        }
+    }
+
+    /// The ffmpeg path can be the binary or its folder, internally the folder is used.
+    pub fn normalize(&mut self) {
+        if let Some(ffmpeg_path) = &self.ffmpeg_path {
+            if ffmpeg_path.is_file() {
+                self.ffmpeg_path = ffmpeg_path.parent().map(Path::to_path_buf);
+            } else if !ffmpeg_path.is_dir() {
+                self.ffmpeg_path = None;
+            }
+        }
+    }
+
+    /// Load and migrate a config file, returns if it was migrated.
+    ///
+    /// It is not validated, overrides like environment variables can still fix values.
+    pub async fn load(path: &Path) -> Result<(Self, bool), ProcessError> {
+        if !path.is_file() {
+            return Err(ProcessError::Custom(format!(
+                "No config file found: {path:?}"
+            )));
+        }
+
+        let contents = fs::read(path).await?;
+        let value = serde_json::from_slice(&contents)
+            .map_err(|e| ProcessError::Custom(format!("{}: {e}", path.display())))?;
+
+        Self::from_value(value)
+    }
+
+    pub async fn save(&self, path: &Path) -> Result<(), ProcessError> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+
+        fs::write(path, serde_json::to_string_pretty(self)?).await?;
+
+        Ok(())
+    }
+
+    /// Override values from `ADCONVERTER_*` environment variables, like `ADCONVERTER_FFMPEG_PATH`.
+    pub fn apply_env(&mut self) -> Result<(), ProcessError> {
+        self.apply_vars(|key| std::env::var(format!("{ENV_PREFIX}{key}")).ok())
+    }
+
+    fn apply_vars(&mut self, var: impl Fn(&str) -> Option<String>) -> Result<(), ProcessError> {
+        let number = |key: &str, value: String| {
+            value.trim().parse::<f64>().map_err(|e| {
+                ProcessError::Custom(format!("Invalid {ENV_PREFIX}{key} \"{value}\": {e}"))
+            })
+        };
+
+        if let Some(value) = var("COPYRIGHT") {
+            self.copyright = value;
+        }
+
+        if let Some(value) = var("FFMPEG_PATH") {
+            self.ffmpeg_path = non_empty_path(&value);
+        }
+
+        if let Some(value) = var("YT_DLP_PATH") {
+            self.yt_dlp_path = non_empty_path(&value);
+        }
+
+        if let Some(value) = var("DOWNLOAD_PATH") {
+            self.download_path = non_empty_path(&value);
+        }
+
+        if let Some(value) = var("DOWNLOAD_ARGS") {
+            self.download_args = value;
+        }
+
+        if let Some(value) = var("SHARED_PRESET_PATH") {
+            self.shared_preset_path = non_empty_path(&value);
+        }
+
+        if let Some(value) = var("LUFS_I") {
+            self.lufs.i = number("LUFS_I", value)?;
+        }
+
+        if let Some(value) = var("LUFS_LRA") {
+            self.lufs.lra = number("LUFS_LRA", value)?;
+        }
+
+        if let Some(value) = var("LUFS_TP") {
+            self.lufs.tp = number("LUFS_TP", value)?;
+        }
+
+        if let Some(value) = var("TRANSCRIPT_CMD") {
+            self.transcript_cmd = value;
+        }
+
+        if let Some(value) = var("DEFAULT_PRESETS") {
+            self.default_presets = value
+                .split(',')
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(ToString::to_string)
+                .collect();
+        }
+
+        Ok(())
+    }
+
+    pub fn code_from(&self, lang: &str) -> String {
+        self.transcript_lang
+            .iter()
+            .find(|l| l.name.eq_ignore_ascii_case(lang) || l.code.eq_ignore_ascii_case(lang))
+            .map(|l| l.code.clone())
+            .unwrap_or_else(|| lang.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ignores_an_empty_yt_dlp_path() {
+        assert_eq!(non_empty_path("  "), None);
+        assert_eq!(
+            non_empty_path("/opt/homebrew/bin/yt-dlp"),
+            Some(PathBuf::from("/opt/homebrew/bin/yt-dlp"))
+        );
+    }
+
+    #[test]
+    fn migrates_empty_paths() {
+        let value = json!({ "ffmpeg_path": "", "yt_dlp_path": " ", "copyright": "ADtv" });
+        let (config, migrated) = Config::from_value(value).unwrap();
+
+        assert!(migrated);
+        assert_eq!(config.version, CONFIG_VERSION);
+        assert_eq!(config.ffmpeg_path, None);
+        assert_eq!(config.yt_dlp_path, None);
+        assert_eq!(config.copyright, "ADtv");
+        assert_eq!(config.lufs.i, -17.0);
+    }
+
+    #[test]
+    fn migrates_the_publisher_to_targets() {
+        let value = json!({
+            "version": 1,
+            "publisher": { "peertube": { "url": "https://tube.example", "channel_id": 3 } }
+        });
+        let (config, migrated) = Config::from_value(value).unwrap();
+
+        assert!(migrated);
+
+        match config.publish_targets.get("PeerTube") {
+            Some(PublishTarget::PeerTube(peertube)) => {
+                assert_eq!(peertube.url, "https://tube.example");
+                assert_eq!(peertube.channel_id, 3);
+            }
+            other => panic!("PeerTube target expected, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn reports_the_invalid_field() {
+        let value = json!({ "version": 1, "lufs": { "i": "loud", "lra": 9.0, "tp": -1.0 } });
+        let error = Config::from_value(value).unwrap_err().to_string();
+
+        assert!(error.contains("lufs.i"), "{error}");
+    }
+
+    #[test]
+    fn rejects_impossible_values() {
+        let config = Config {
+            ffmpeg_path: Some(PathBuf::from("/does/not/exist/ffmpeg")),
+            lufs: LufsConfig {
+                i: 3.0,
+                ..Default::default()
+            },
+            transcript_cmd: "transcript.py -f %file%".to_string(),
+            ..Default::default()
+        };
+        let error = config.validate().unwrap_err().to_string();
+
+        assert!(error.contains("ffmpeg_path"), "{error}");
+        assert!(error.contains("lufs.i"), "{error}");
+        assert!(!error.contains("lufs.tp"), "{error}");
+        assert!(error.contains("%lang%"), "{error}");
+    }
+
+    #[test]
+    fn only_the_active_profile_fails() {
+        let mut config = Config {
+            yt_dlp_path: Some(PathBuf::from("yt-dlp")),
+            profiles: BTreeMap::from([(
+                "broken".to_string(),
+                Profile {
+                    transcript_cmd: Some("transcript.py".to_string()),
+                    ..Default::default()
+                },
+            )]),
+            ..Default::default()
+        };
+
+        assert!(config.validate().is_ok());
+
+        config.profile = Some("broken".to_string());
+        let error = config.validate().unwrap_err().to_string();
+
+        assert!(error.contains("profiles.broken.transcript_cmd"), "{error}");
+    }
+
+    #[test]
+    fn profile_replaces_base_values() {
+        let value = json!({
+            "version": 1,
+            "copyright": "A",
+            "download_args": "-f best",
+            "profile": "web",
+            "profiles": {
+                "broadcast": { "lufs": { "i": -23.0, "lra": 7.0, "tp": -1.0 } },
+                "web": { "copyright": "B", "lufs": { "i": -14.0, "lra": 9.0, "tp": -1.0 } }
+            }
+        });
+        let (config, _) = Config::from_value(value).unwrap();
+        let active = config.active();
+
+        assert_eq!(active.profile.as_deref(), Some("web"));
+        assert_eq!(active.copyright, "B");
+        assert_eq!(active.lufs.i, -14.0);
+        assert_eq!(active.download_args, "-f best");
+
+        let broadcast = config.with_profile("broadcast").unwrap();
+
+        assert_eq!(broadcast.copyright, "A");
+        assert_eq!(broadcast.lufs.i, -23.0);
+        assert!(config.with_profile("radio").is_err());
+    }
+
+    #[test]
+    fn env_overrides_values() {
+        let vars = |key: &str| match key {
+            "FFMPEG_PATH" => Some("/opt/ffmpeg/bin".to_string()),
+            "LUFS_I" => Some("-23".to_string()),
+            "DEFAULT_PRESETS" => Some("web, archive".to_string()),
+            _ => None,
+        };
+        let mut config = Config::default();
+
+        config.apply_vars(vars).unwrap();
+
+        assert_eq!(config.ffmpeg_path, Some(PathBuf::from("/opt/ffmpeg/bin")));
+        assert_eq!(config.lufs.i, -23.0);
+        assert_eq!(config.lufs.tp, -1.0);
+        assert_eq!(config.default_presets, ["web", "archive"]);
+        assert!(
+            config
+                .apply_vars(|key| (key == "LUFS_TP").then(|| "loud".to_string()))
+                .is_err()
+        );
+    }
+}
@@ -1,16 +1,51 @@
 use std::{path::PathBuf, process::Stdio, sync::Arc};
 
 use log::debug;
-use tauri::{AppHandle, Emitter};
 use tokio::{
     io::{AsyncBufReadExt, BufReader},
     process::{Child, Command},
     sync::Mutex,
 };
 
-use crate::utils::errors::ProcessError;
+use crate::{
+    Config,
+    utils::{config::DEFAULT_DOWNLOAD_ARGS, errors::ProcessError},
+};
 
 const OUTPUT_MARKER: &str = "__ADCONVERTER_OUTPUT__";
+/// yt-dlp download archive in the download folder, archived items are not downloaded again.
+const ARCHIVE_FILE: &str = ".download-archive.txt";
+
+/// Download progress, `item` of `count` for playlists, 1 of 1 otherwise.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Progress {
+    pub item: usize,
+    pub count: usize,
+    pub percent: f64,
+}
+
+impl Default for Progress {
+    fn default() -> Self {
+        Self {
+            item: 1,
+            count: 1,
+            percent: 0.0,
+        }
+    }
+}
+
+pub fn is_url(input: &str) -> bool {
+    input.starts_with("http://") || input.starts_with("https://")
+}
+
+/// yt-dlp arguments of the config, the defaults when they are empty.
+pub fn arguments(config: &Config) -> String {
+    if config.download_args.trim().is_empty() {
+        DEFAULT_DOWNLOAD_ARGS.to_string()
+    } else {
+        config.download_args.clone()
+    }
+}
 
 pub async fn version(path: Option<PathBuf>) -> Result<String, ProcessError> {
     version_from_program(program(path)).await
@@ -41,24 +76,57 @@ async fn version_from_program(program: PathBuf) -> Result<String, ProcessError>
     Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
 }
 
-/// Download one media URL and report yt-dlp's percentage to the webview.
+/// Download a media or playlist URL, returns the new files.
+///
+/// Without `playlist` and `playlist_items` only the video of a URL is downloaded, also when
+/// the URL names a playlist too. In playlist mode the items can be limited with yt-dlp's
+/// `playlist_items` range, like `1-5,8`, and items from the download archive are skipped,
+/// so the result can be empty.
+/// Info JSON and thumbnail are saved next to each file, see [`SourceMetadata`](super::metadata::SourceMetadata).
 pub async fn download(
-    app: AppHandle,
     url: String,
     directory: PathBuf,
-    arguments: &str,
-    yt_dlp_path: Option<PathBuf>,
+    config: &Config,
+    playlist: bool,
+    playlist_items: Option<&str>,
     process: Arc<Mutex<Option<Child>>>,
-) -> Result<String, ProcessError> {
+    progress: impl Fn(Progress),
+) -> Result<Vec<String>, ProcessError> {
     tokio::fs::create_dir_all(&directory).await?;
 
-    let arguments = parse_arguments(arguments)?;
-    eprintln!("[yt-dlp] starting download for {url} with arguments: {arguments:?}");
-    let mut command = Command::new(program(yt_dlp_path));
+    let arguments = parse_arguments(&arguments(config))?;
+    debug!("Start download of {url} with arguments: {arguments:?}");
+    let mut command = Command::new(program(config.yt_dlp_path.clone()));
+    command.kill_on_drop(true).args(arguments).args([
+        "--newline",
+        "--progress",
+        "--write-info-json",
+        "--write-thumbnail",
+        "--convert-thumbnails",
+        "jpg",
+    ]);
+
+    // The thumbnail conversion runs ffmpeg, use the one of the config like the encoder.
+    if let Some(ffmpeg_path) = &config.ffmpeg_path {
+        command.arg("--ffmpeg-location").arg(ffmpeg_path);
+    }
+
+    let items = playlist_items.map(str::trim).filter(|i| !i.is_empty());
+    let playlist = playlist || items.is_some();
+
+    if playlist {
+        command
+            .args(["--yes-playlist", "--download-archive"])
+            .arg(directory.join(ARCHIVE_FILE));
+
+        if let Some(items) = items {
+            command.args(["--playlist-items", items]);
+        }
+    } else {
+        command.arg("--no-playlist");
+    }
+
     command
-        .kill_on_drop(true)
-        .args(arguments)
-        .args(["--no-playlist", "--newline", "--progress"])
         .arg("--print")
         .arg(format!("after_move:{OUTPUT_MARKER}%(filepath)s"))
         .arg(&url)
@@ -87,7 +155,8 @@ pub async fn download(
     let mut stdout = BufReader::new(stdout).lines();
     let mut stderr = BufReader::new(stderr).lines();
     *process.lock().await = Some(child);
-    let mut downloaded_file = None;
+    let mut files = vec![];
+    let mut state = Progress::default();
     let mut error_output = Vec::new();
     let mut stdout_open = true;
     let mut stderr_open = true;
@@ -95,14 +164,13 @@ pub async fn download(
     while stdout_open || stderr_open {
         tokio::select! {
             line = stdout.next_line(), if stdout_open => match line? {
-                Some(line) => process_line(&app, &line, &mut downloaded_file),
+                Some(line) => process_line(&line, &mut files, &mut state, &progress),
                 None => stdout_open = false,
             },
             line = stderr.next_line(), if stderr_open => match line? {
                 Some(line) => {
                     debug!("yt-dlp: {line}");
-                    eprintln!("[yt-dlp] {line}");
-                    process_line(&app, &line, &mut downloaded_file);
+                    process_line(&line, &mut files, &mut state, &progress);
                     if !line.trim().is_empty() {
                         error_output.push(line);
                     }
@@ -131,9 +199,13 @@ pub async fn download(
         )));
     }
 
-    downloaded_file.ok_or_else(|| {
-        ProcessError::Custom("yt-dlp did not return the downloaded file path".into())
-    })
+    if files.is_empty() && !playlist {
+        return Err(ProcessError::Custom(format!(
+            "yt-dlp saved no file of {url}"
+        )));
+    }
+
+    Ok(files)
 }
 
 fn program(path: Option<PathBuf>) -> PathBuf {
@@ -144,14 +216,29 @@ fn program(path: Option<PathBuf>) -> PathBuf {
     }
 }
 
-fn process_line(app: &AppHandle, line: &str, downloaded_file: &mut Option<String>) {
+fn process_line(
+    line: &str,
+    files: &mut Vec<String>,
+    state: &mut Progress,
+    progress: &impl Fn(Progress),
+) {
     if let Some(path) = output_path(line) {
-        *downloaded_file = Some(path.to_string());
+        files.push(path.to_string());
+    }
+
+    if let Some((item, count)) = playlist_item(line) {
+        *state = Progress {
+            item,
+            count,
+            percent: 0.0,
+        };
+        progress(*state);
     }
 
     // yt-dlp's --newline output contains values such as "[download]  42.1%".
     if let Some(percent) = progress_percent(line) {
-        let _ = app.emit("download-progress", percent);
+        state.percent = percent;
+        progress(*state);
     }
 }
 
@@ -164,6 +251,17 @@ fn output_path(line: &str) -> Option<&str> {
     line.strip_prefix(OUTPUT_MARKER)
 }
 
+/// Parse "[download] Downloading item 2 of 5", older versions write "video" instead of "item".
+fn playlist_item(line: &str) -> Option<(usize, usize)> {
+    let rest = line
+        .split_once("Downloading item ")
+        .or_else(|| line.split_once("Downloading video "))?
+        .1;
+    let (item, count) = rest.trim().split_once(" of ")?;
+
+    Some((item.parse().ok()?, count.parse().ok()?))
+}
+
 fn progress_percent(line: &str) -> Option<f64> {
     line.split_whitespace()
         .find_map(|part| part.strip_suffix('%'))
@@ -195,6 +293,28 @@ mod tests {
         assert_eq!(progress_percent("no progress"), None);
     }
 
+    #[test]
+    fn parses_playlist_items() {
+        assert_eq!(
+            playlist_item("[download] Downloading item 2 of 15"),
+            Some((2, 15))
+        );
+        assert_eq!(
+            playlist_item("[download] Downloading video 3 of 4"),
+            Some((3, 4))
+        );
+        assert_eq!(
+            playlist_item("[download] Downloading playlist: Talks"),
+            None
+        );
+    }
+
+    #[test]
+    fn detects_urls() {
+        assert!(is_url("https://example.org/video"));
+        assert!(!is_url("/media/https/video.mp4"));
+    }
+
     #[test]
     fn extracts_only_marked_output_paths() {
         assert_eq!(
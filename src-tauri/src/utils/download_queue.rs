@@ -0,0 +1,324 @@
+use std::{
+    collections::HashSet,
+    path::Path,
+    sync::{Arc, atomic::AtomicBool},
+};
+
+use log::*;
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::{
+    process::Child,
+    sync::{Mutex, mpsc::Receiver},
+};
+use tokio_util::sync::CancellationToken;
+use ts_rs::TS;
+
+use crate::{
+    AppState, Config, ProcessError, Task,
+    ffmpeg::probe::MediaProbe,
+    resolve_download_directory,
+    utils::{Sources, download, http_download, metadata::SourceMetadata, template::Template},
+};
+
+#[derive(Clone, Debug, Serialize, TS)]
+#[ts(export, export_to = "backend.d.ts")]
+pub struct DownloadProgress {
+    pub id: String,
+    /// Playlist item, starts with 1.
+    pub item: usize,
+    pub count: usize,
+    pub percent: f64,
+}
+
+#[derive(Clone, Debug, Serialize, TS)]
+#[ts(export, export_to = "backend.d.ts")]
+pub struct DownloadError {
+    pub id: String,
+    pub message: String,
+    /// Cancelled by the user, not failed.
+    pub cancelled: bool,
+}
+
+/// State of the download queue, the queued tasks are in the channel.
+#[derive(Clone, Default)]
+pub struct DownloadQueue {
+    /// Process of the running download.
+    pub process: Arc<Mutex<Option<Child>>>,
+    /// Stops a running HTTP download.
+    token: Arc<Mutex<CancellationToken>>,
+    current: Arc<Mutex<Option<String>>>,
+    /// Tasks in the channel, which are not started yet.
+    queued: Arc<Mutex<HashSet<String>>>,
+    cancelled: Arc<Mutex<HashSet<String>>>,
+}
+
+impl DownloadQueue {
+    /// Remember a task, which is sent to the download channel.
+    pub async fn queue(&self, id: &str) {
+        self.queued.lock().await.insert(id.to_string());
+    }
+
+    /// Forget a task, which could not be sent to the download channel.
+    pub async fn unqueue(&self, id: &str) {
+        self.queued.lock().await.remove(id);
+    }
+
+    /// Cancel a queued or running download, other ids are ignored.
+    pub async fn cancel(&self, id: &str) -> Result<(), ProcessError> {
+        let is_queued = self.queued.lock().await.contains(id);
+        let is_current = self.current.lock().await.as_deref() == Some(id);
+
+        if !is_queued && !is_current {
+            return Ok(());
+        }
+
+        self.cancelled.lock().await.insert(id.to_string());
+
+        if !is_current {
+            return Ok(());
+        }
+
+        self.token.lock().await.cancel();
+
+        if let Some(mut proc) = self.process.lock().await.take() {
+            proc.kill().await?;
+            proc.wait().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Mark a download as running, returns its cancellation token.
+    async fn start(&self, id: &str) -> CancellationToken {
+        let token = CancellationToken::new();
+
+        *self.current.lock().await = Some(id.to_string());
+        *self.token.lock().await = token.clone();
+        self.queued.lock().await.remove(id);
+
+        token
+    }
+
+    async fn take_cancelled(&self, id: &str) -> bool {
+        self.cancelled.lock().await.remove(id)
+    }
+}
+
+/// Download the task URL, each new file becomes a task, probed and with its template like a dropped file.
+///
+/// A file which can not be prepared fails alone, the other files are still returned.
+async fn fetch(
+    app: &AppHandle,
+    state: &AppState,
+    task: &Task,
+    token: CancellationToken,
+) -> Result<Vec<Result<Task, DownloadError>>, ProcessError> {
+    let url = task.url.clone().unwrap_or_default();
+    let config = state.config.lock().await.clone();
+    let config = match &task.profile {
+        Some(name) => config.with_profile(name)?,
+        None => config.active(),
+    };
+
+    // A task from a cancelled run can already be downloaded.
+    let files = if Path::new(&task.path).is_file() {
+        vec![task.path.clone()]
+    } else {
+        let directory = resolve_download_directory(
+            task.target.clone(),
+            config.download_path.clone(),
+            dirs::download_dir(),
+        )
+        .ok_or_else(|| ProcessError::Custom("Could not determine the Downloads folder".into()))?;
+
+        let progress = |progress: download::Progress| {
+            let progress = DownloadProgress {
+                id: task.id.clone(),
+                item: progress.item,
+                count: progress.count,
+                percent: progress.percent,
+            };
+            let _ = app.emit("download-progress", progress);
+        };
+
+        // Direct media links are downloaded natively, site URLs need yt-dlp.
+        match http_download::download(&url, &directory, task.checksum.as_deref(), token, &progress)
+            .await?
+        {
+            Some(file) => vec![file],
+            None => {
+                download::download(
+                    url,
+                    directory,
+                    &config,
+                    task.playlist,
+                    task.playlist_items.as_deref(),
+                    state.downloads.process.clone(),
+                    &progress,
+                )
+                .await?
+            }
+        }
+    };
+
+    let mut tasks = vec![];
+
+    for (i, file) in files.into_iter().enumerate() {
+        let mut item = task.clone();
+
+        // The first file keeps the queued task, further playlist items become new tasks.
+        if i > 0 {
+            item.id = format!("{}-{}", task.id, i + 1);
+            item.active = Arc::new(AtomicBool::new(true));
+            item.finished = Arc::new(AtomicBool::new(false));
+        }
+
+        let id = item.id.clone();
+
+        tasks.push(
+            prepare(&config, item, &file)
+                .await
+                .map_err(|e| DownloadError {
+                    id,
+                    message: format!("{file}: {e}"),
+                    cancelled: false,
+                }),
+        );
+    }
+
+    Ok(tasks)
+}
+
+/// Probe a downloaded file and add its template and metadata to the task.
+async fn prepare(config: &Config, mut item: Task, file: &str) -> Result<Task, ProcessError> {
+    item.probe = MediaProbe::new(config, file).await?;
+    item.template = match Sources::new(file).await?.template {
+        Some(src) => Some(Template::new(&src).await?),
+        None => None,
+    };
+    item.metadata = SourceMetadata::read(file).await;
+
+    if let (Some(publish), Some(metadata)) = (item.publish.as_mut(), &item.metadata) {
+        publish.fill(metadata);
+    }
+
+    item.path = file.to_string();
+
+    Ok(item)
+}
+
+/// Emit an event, a failed emit only is logged and doesn't stop the queue.
+fn emit(app: &AppHandle, event: &str, payload: impl Serialize + Clone) {
+    if let Err(e) = app.emit(event, payload) {
+        error!("Could not emit {event}: {e}");
+    }
+}
+
+/// Download queued URL tasks one after another and hand them to the encode queue.
+pub async fn run(app: AppHandle, mut rx: Receiver<Task>) -> Result<(), ProcessError> {
+    let state = app.state::<AppState>().to_owned();
+    let queue = state.downloads.clone();
+
+    while let Some(task) = rx.recv().await {
+        let token = queue.start(&task.id).await;
+
+        if queue.take_cancelled(&task.id).await {
+            *queue.current.lock().await = None;
+            emit(
+                &app,
+                "download-error",
+                DownloadError {
+                    id: task.id.clone(),
+                    message: "Download cancelled".to_string(),
+                    cancelled: true,
+                },
+            );
+
+            continue;
+        }
+
+        emit(&app, "download-start", &task);
+
+        let mut result = fetch(&app, &state, &task, token).await;
+
+        *queue.current.lock().await = None;
+
+        // A cancel can arrive after the download, while the files are probed.
+        let cancelled = queue.take_cancelled(&task.id).await;
+
+        if cancelled && result.is_ok() {
+            result = Err(ProcessError::Custom("Download cancelled".to_string()));
+        }
+
+        match result {
+            Ok(tasks) if tasks.is_empty() => {
+                emit(
+                    &app,
+                    "download-skip",
+                    DownloadError {
+                        id: task.id.clone(),
+                        message: "All items are in the download archive".to_string(),
+                        cancelled: false,
+                    },
+                );
+            }
+            Ok(tasks) => {
+                for task in tasks {
+                    match task {
+                        Ok(task) => {
+                            emit(&app, "download-finish", &task);
+
+                            if let Err(e) = state.sender.send(task).await {
+                                error!("Could not queue the downloaded task: {e}");
+                            }
+                        }
+                        Err(e) => {
+                            error!("Downloaded item failed: {}", e.message);
+                            emit(&app, "download-error", e);
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                if !cancelled {
+                    error!(
+                        "Download of {:?} failed: {e}",
+                        task.url.as_deref().unwrap_or_default()
+                    );
+                }
+
+                emit(
+                    &app,
+                    "download-error",
+                    DownloadError {
+                        id: task.id.clone(),
+                        message: e.to_string(),
+                        cancelled,
+                    },
+                );
+            }
+        }
+    }
+
+    warn!("Download worker done...");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn remembers_cancels_of_known_downloads() {
+        let queue = DownloadQueue::default();
+
+        queue.cancel("unknown").await.unwrap();
+        queue.queue("queued").await;
+        queue.cancel("queued").await.unwrap();
+
+        assert!(!queue.take_cancelled("unknown").await);
+        assert!(queue.take_cancelled("queued").await);
+    }
+}
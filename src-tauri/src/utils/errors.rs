@@ -34,6 +34,12 @@ impl From<std::io::Error> for ProcessError {
     }
 }
 
+impl From<zip::result::ZipError> for ProcessError {
+    fn from(err: zip::result::ZipError) -> ProcessError {
+        ProcessError::IO(err.to_string())
+    }
+}
+
 impl From<std::path::StripPrefixError> for ProcessError {
     fn from(err: std::path::StripPrefixError) -> ProcessError {
         ProcessError::IO(err.to_string())
@@ -0,0 +1,575 @@
+use std::path::{Path, PathBuf};
+
+use log::*;
+use sha2::{Digest, Sha256, Sha512};
+use tauri_plugin_http::reqwest::{
+    Client, Response, StatusCode,
+    header::{
+        ACCEPT_RANGES, CONTENT_DISPOSITION, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, ETAG,
+        IF_RANGE, LAST_MODIFIED, RANGE,
+    },
+};
+use tokio::{
+    fs::{self, File, OpenOptions},
+    io::{AsyncReadExt, AsyncWriteExt},
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::utils::{AUDIO_EXTENSIONS, VIDEO_EXTENSIONS, download::Progress, errors::ProcessError};
+
+/// Suffix of unfinished downloads, they are resumed on the next try.
+const PART_EXTENSION: &str = "part";
+
+/// Expected hash of a download, given as `sha256:<hex>`, `sha512:<hex>` or only the hex digest.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Checksum {
+    Sha256(String),
+    Sha512(String),
+}
+
+impl Checksum {
+    pub fn parse(value: &str) -> Result<Self, ProcessError> {
+        let value = value.trim().to_lowercase();
+        let (algorithm, digest) = match value.split_once(':') {
+            Some((algorithm, digest)) => (algorithm.to_string(), digest.to_string()),
+            None => match value.len() {
+                64 => ("sha256".to_string(), value.clone()),
+                128 => ("sha512".to_string(), value.clone()),
+                _ => (String::new(), value.clone()),
+            },
+        };
+
+        if !digest.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(ProcessError::Custom(format!(
+                "Checksum is not a hex digest: {digest}"
+            )));
+        }
+
+        match algorithm.as_str() {
+            "sha256" if digest.len() == 64 => Ok(Self::Sha256(digest)),
+            "sha512" if digest.len() == 128 => Ok(Self::Sha512(digest)),
+            _ => Err(ProcessError::Custom(format!(
+                "Unsupported checksum \"{value}\", use sha256:<hex> or sha512:<hex>"
+            ))),
+        }
+    }
+
+    /// Compare with the hash of a file.
+    pub async fn verify(&self, path: &Path) -> Result<(), ProcessError> {
+        let actual = match self {
+            Self::Sha256(_) => hash_file::<Sha256>(path).await?,
+            Self::Sha512(_) => hash_file::<Sha512>(path).await?,
+        };
+        let (Self::Sha256(expected) | Self::Sha512(expected)) = self;
+
+        if &actual != expected {
+            return Err(ProcessError::Custom(format!(
+                "Checksum mismatch of {path:?}, expected {expected}, got {actual}"
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+async fn hash_file<D: Digest>(path: &Path) -> Result<String, ProcessError> {
+    let mut file = File::open(path).await?;
+    let mut hasher = D::new();
+    let mut buffer = vec![0; 1024 * 1024];
+
+    loop {
+        let read = file.read(&mut buffer).await?;
+
+        if read == 0 {
+            break;
+        }
+
+        hasher.update(&buffer[..read]);
+    }
+
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect())
+}
+
+pub fn decode_percent(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && let Some(byte) = value
+                .get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        {
+            decoded.push(byte);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+/// File name of a `Content-Disposition` header, `filename*` is preferred.
+fn disposition_name(header: &str) -> Option<String> {
+    let mut plain = None;
+
+    for part in header.split(';').map(str::trim) {
+        let Some((key, value)) = part.split_once('=') else {
+            continue;
+        };
+
+        match key.trim().to_lowercase().as_str() {
+            // RFC 5987: filename*=UTF-8''name%20with%20spaces
+            "filename*" => {
+                let encoded = value.rsplit_once('\'').map_or(value, |(_, v)| v);
+
+                return Some(decode_percent(encoded.trim_matches('"')));
+            }
+            "filename" => plain = Some(value.trim().trim_matches('"').to_string()),
+            _ => {}
+        }
+    }
+
+    plain
+}
+
+/// Name of the downloaded file, without folders and never empty.
+fn file_name(url: &str, disposition: Option<&str>) -> String {
+    let from_url = || {
+        let path = url.split(['?', '#']).next().unwrap_or_default();
+        let path = path.split_once("://").map_or(path, |(_, rest)| rest);
+
+        path.split_once('/')
+            .and_then(|(_, path)| path.rsplit('/').next())
+            .map(decode_percent)
+    };
+    let name = disposition
+        .and_then(disposition_name)
+        .or_else(from_url)
+        .unwrap_or_default();
+    let name = name
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .trim_start_matches('.');
+
+    if name.is_empty() {
+        "download".to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+fn has_media_extension(name: &str) -> bool {
+    let extension = Path::new(name)
+        .extension()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_lowercase();
+
+    VIDEO_EXTENSIONS.contains(&extension.as_str()) || AUDIO_EXTENSIONS.contains(&extension.as_str())
+}
+
+/// URL path ends with a media file extension.
+fn is_media_link(url: &str) -> bool {
+    has_media_extension(&file_name(url, None))
+}
+
+fn header<'a>(response: &'a Response, name: impl AsRef<str>) -> Option<&'a str> {
+    response
+        .headers()
+        .get(name.as_ref())
+        .and_then(|value| value.to_str().ok())
+}
+
+/// Content types of media files, other types like web pages or JSON are no downloads.
+/// Generic binary types are accepted for file names with a media extension.
+fn is_media(content_type: Option<&str>, name: &str) -> bool {
+    let content_type = content_type
+        .and_then(|t| t.split(';').next())
+        .unwrap_or_default()
+        .trim()
+        .to_lowercase();
+
+    match content_type.as_str() {
+        t if t.starts_with("video/") || t.starts_with("audio/") => true,
+        "application/mp4" | "application/ogg" | "application/mxf" => true,
+        "" | "application/octet-stream" | "binary/octet-stream" | "application/x-download" => {
+            has_media_extension(name)
+        }
+        _ => false,
+    }
+}
+
+/// Size of the whole file, also of a partial response.
+fn total_size(response: &Response) -> Option<u64> {
+    if let Some(range) = header(response, CONTENT_RANGE) {
+        return range
+            .rsplit_once('/')
+            .and_then(|(_, size)| size.parse().ok());
+    }
+
+    // Not `Response::content_length`, which is the body size and empty for `HEAD`.
+    header(response, CONTENT_LENGTH).and_then(|len| len.parse().ok())
+}
+
+/// Strong `ETag` or `Last-Modified` for `If-Range`, weak tags are not allowed there.
+fn validator(response: &Response) -> Option<String> {
+    header(response, ETAG)
+        .filter(|tag| !tag.starts_with("W/"))
+        .or_else(|| header(response, LAST_MODIFIED))
+        .map(str::to_string)
+}
+
+/// Headers of a URL without the body: `HEAD`, or a one byte range when `HEAD` is refused,
+/// like by presigned links, which are only signed for `GET`.
+async fn probe(client: &Client, url: &str) -> Result<Response, ProcessError> {
+    let response = client.head(url).send().await?;
+
+    if response.status().is_success() {
+        return Ok(response);
+    }
+
+    debug!("HEAD of {url} answered with {}", response.status());
+
+    Ok(client.get(url).header(RANGE, "bytes=0-0").send().await?)
+}
+
+fn part_path(target: &Path) -> PathBuf {
+    let name = target.file_name().unwrap_or_default().to_string_lossy();
+
+    target.with_file_name(format!(".{name}.{PART_EXTENSION}"))
+}
+
+/// Validator of the remote file, from which the part was started.
+fn tag_path(part: &Path) -> PathBuf {
+    part.with_extension(format!("{PART_EXTENSION}.tag"))
+}
+
+/// `name.ext`, `name (1).ext`, `name (2).ext`, ...
+fn numbered(name: &str, number: usize) -> String {
+    if number == 0 {
+        return name.to_string();
+    }
+
+    let path = Path::new(name);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+
+    match path.extension() {
+        Some(extension) => format!("{stem} ({number}).{}", extension.to_string_lossy()),
+        None => format!("{stem} ({number})"),
+    }
+}
+
+/// Path of the download, other files with the same name are kept.
+///
+/// Returns `true` when the file is already downloaded.
+async fn target_path(
+    directory: &Path,
+    name: &str,
+    total: Option<u64>,
+) -> Result<(PathBuf, bool), ProcessError> {
+    for number in 0.. {
+        let target = directory.join(numbered(name, number));
+
+        if part_path(&target).is_file() || !target.exists() {
+            return Ok((target, false));
+        }
+
+        if total.is_some() && total == Some(fs::metadata(&target).await?.len()) {
+            return Ok((target, true));
+        }
+    }
+
+    unreachable!()
+}
+
+/// Download a direct media link into `directory`, returns the path of the file.
+///
+/// Unfinished downloads are resumed with a range request, when the server supports it and
+/// the remote file is unchanged. Existing files with the same name are not replaced.
+/// Returns `None` when the URL is no media file, like a web page, which is left to yt-dlp.
+pub async fn download(
+    url: &str,
+    directory: &Path,
+    checksum: Option<&str>,
+    cancel: CancellationToken,
+    progress: impl Fn(Progress),
+) -> Result<Option<String>, ProcessError> {
+    let checksum = checksum
+        .filter(|c| !c.trim().is_empty())
+        .map(Checksum::parse)
+        .transpose()?;
+    let client = Client::builder()
+        .user_agent(format!("adConverter/{}", env!("CARGO_PKG_VERSION")))
+        .build()?;
+    let head = probe(&client, url).await?;
+
+    // Sites can refuse requests which are not from a browser, yt-dlp knows how to handle them.
+    if !head.status().is_success() && !is_media_link(url) {
+        debug!(
+            "{url} answered with {}, download with yt-dlp",
+            head.status()
+        );
+        return Ok(None);
+    }
+
+    let head = head.error_for_status()?;
+    let name = file_name(url, header(&head, CONTENT_DISPOSITION));
+
+    if !is_media(header(&head, CONTENT_TYPE), &name) {
+        let content_type = header(&head, CONTENT_TYPE).unwrap_or("no content type");
+
+        if is_media_link(url) {
+            return Err(ProcessError::Custom(format!(
+                "{url} is no media file, the server sent {content_type}"
+            )));
+        }
+
+        debug!("{url} is {content_type}, download with yt-dlp");
+        return Ok(None);
+    }
+
+    let total = total_size(&head);
+    let remote_tag = validator(&head);
+    let ranges = header(&head, ACCEPT_RANGES) == Some("bytes")
+        || head.status() == StatusCode::PARTIAL_CONTENT;
+    drop(head);
+
+    fs::create_dir_all(directory).await?;
+
+    let (target, done) = target_path(directory, &name, total).await?;
+    let part = part_path(&target);
+    let tag = tag_path(&part);
+
+    if done {
+        debug!("{target:?} is already downloaded");
+
+        if let Some(checksum) = &checksum {
+            checksum.verify(&target).await?;
+        }
+
+        return Ok(Some(target.to_string_lossy().to_string()));
+    }
+
+    let offset = match fs::metadata(&part).await {
+        Ok(meta) => meta.len(),
+        Err(_) => 0,
+    };
+    let stored_tag = fs::read_to_string(&tag).await.ok();
+    let mut request = client.get(url);
+
+    // A part can only be continued, when the server can tell if the file changed since.
+    if offset > 0
+        && ranges
+        && let Some(stored_tag) = &stored_tag
+    {
+        debug!("Resume download of {url} at byte {offset}");
+        request = request
+            .header(RANGE, format!("bytes={offset}-"))
+            .header(IF_RANGE, stored_tag);
+    }
+
+    let mut response = request.send().await?;
+    let complete = response.status() == StatusCode::RANGE_NOT_SATISFIABLE
+        && total_size(&response).or(total) == Some(offset);
+
+    if response.status() == StatusCode::RANGE_NOT_SATISFIABLE && !complete {
+        debug!("Range of {url} is not satisfiable, download again");
+        response = client.get(url).send().await?;
+    }
+
+    if !complete {
+        response = response.error_for_status()?;
+    }
+
+    // Without a partial response the server sends the full file again.
+    let resumed = complete || response.status() == StatusCode::PARTIAL_CONTENT;
+    let mut file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(resumed)
+        .truncate(!resumed)
+        .open(&part)
+        .await?;
+
+    if !resumed {
+        match &remote_tag {
+            Some(remote_tag) => fs::write(&tag, remote_tag).await?,
+            None => {
+                let _ = fs::remove_file(&tag).await;
+            }
+        }
+    }
+
+    let mut received = if resumed { offset } else { 0 };
+    let size = if complete {
+        Some(offset)
+    } else {
+        total_size(&response).or(if resumed { None } else { total })
+    };
+
+    while !complete {
+        let chunk = tokio::select! {
+            chunk = response.chunk() => chunk?,
+            _ = cancel.cancelled() => {
+                file.flush().await?;
+                return Err(ProcessError::Custom("Download was cancelled".into()));
+            }
+        };
+        let Some(chunk) = chunk else {
+            break;
+        };
+
+        file.write_all(&chunk).await?;
+        received += chunk.len() as u64;
+
+        if let Some(size) = size.filter(|s| *s > 0) {
+            progress(Progress {
+                percent: (received as f64 / size as f64 * 100.0).min(100.0),
+                ..Default::default()
+            });
+        }
+    }
+
+    file.flush().await?;
+    drop(file);
+
+    if let Some(size) = size
+        && received != size
+    {
+        return Err(ProcessError::Custom(format!(
+            "Download of {url} is incomplete, received {received} of {size} bytes"
+        )));
+    }
+
+    if let Some(checksum) = &checksum
+        && let Err(e) = checksum.verify(&part).await
+    {
+        fs::remove_file(&part).await?;
+        let _ = fs::remove_file(&tag).await;
+
+        return Err(e);
+    }
+
+    fs::rename(&part, &target).await?;
+    let _ = fs::remove_file(&tag).await;
+
+    Ok(Some(target.to_string_lossy().to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_checksums() {
+        let sha256 = "a".repeat(64);
+
+        assert_eq!(
+            Checksum::parse(&format!("SHA256:{}", sha256.to_uppercase())).ok(),
+            Some(Checksum::Sha256(sha256.clone()))
+        );
+        assert_eq!(
+            Checksum::parse(&sha256).ok(),
+            Some(Checksum::Sha256(sha256))
+        );
+        assert!(Checksum::parse("md5:d41d8cd98f00b204e9800998ecf8427e").is_err());
+        assert!(Checksum::parse(&format!("sha256:{}", "x".repeat(64))).is_err());
+    }
+
+    #[test]
+    fn names_files() {
+        assert_eq!(
+            file_name(
+                "https://example.org/a/clip.mp4",
+                Some("attachment; filename=\"show.mov\"; filename*=UTF-8''show%20final.mov")
+            ),
+            "show final.mov"
+        );
+        assert_eq!(
+            file_name(
+                "https://example.org/files/a%20b.mp4?X-Amz-Signature=1",
+                None
+            ),
+            "a b.mp4"
+        );
+        assert_eq!(
+            file_name("https://example.org/", Some("attachment; filename=../.x")),
+            "x"
+        );
+        assert_eq!(file_name("https://example.org", None), "download");
+    }
+
+    #[test]
+    fn accepts_media_types() {
+        assert!(is_media(Some("video/mp4"), "download"));
+        assert!(is_media(Some("Audio/MPEG; charset=binary"), "a"));
+        assert!(is_media(Some("application/octet-stream"), "talk.mov"));
+        assert!(!is_media(Some("application/octet-stream"), "archive.zip"));
+        assert!(!is_media(Some("application/json"), "talk.mp4"));
+        assert!(!is_media(Some("text/html; charset=utf-8"), "watch"));
+    }
+
+    #[tokio::test]
+    async fn keeps_existing_files() {
+        let directory = std::env::temp_dir().join("adconverter-http-download-test");
+        let _ = fs::remove_dir_all(&directory).await;
+        fs::create_dir_all(&directory).await.unwrap();
+        fs::write(directory.join("talk.mp4"), "other")
+            .await
+            .unwrap();
+
+        assert_eq!(
+            target_path(&directory, "talk.mp4", Some(10)).await.unwrap(),
+            (directory.join("talk (1).mp4"), false)
+        );
+        assert_eq!(
+            target_path(&directory, "talk.mp4", Some(5)).await.unwrap(),
+            (directory.join("talk.mp4"), true)
+        );
+
+        fs::write(part_path(&directory.join("talk (1).mp4")), "tal")
+            .await
+            .unwrap();
+        fs::write(directory.join("talk (1).mp4"), "x")
+            .await
+            .unwrap();
+
+        assert_eq!(
+            target_path(&directory, "talk.mp4", Some(10)).await.unwrap(),
+            (directory.join("talk (1).mp4"), false)
+        );
+
+        fs::remove_dir_all(directory).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn verifies_file_hashes() {
+        let path = std::env::temp_dir().join("adconverter-checksum-test.txt");
+        fs::write(&path, "abc").await.unwrap();
+
+        let checksum = Checksum::parse(
+            "sha256:ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+        )
+        .unwrap();
+
+        assert!(checksum.verify(&path).await.is_ok());
+        assert!(
+            Checksum::Sha256("0".repeat(64))
+                .verify(&path)
+                .await
+                .is_err()
+        );
+
+        fs::remove_file(path).await.unwrap();
+    }
+}
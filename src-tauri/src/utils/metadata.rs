@@ -0,0 +1,206 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use serde_with::{DefaultOnNull, serde_as};
+use tokio::fs;
+use ts_rs::TS;
+
+use crate::{Publish, vec_strings};
+
+/// Thumbnail formats yt-dlp writes, in order of preference.
+const THUMBNAIL_EXTENSIONS: [&str; 4] = ["jpg", "png", "webp", "jpeg"];
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, TS)]
+#[ts(export, export_to = "backend.d.ts")]
+pub struct Chapter {
+    pub start_time: f64,
+    pub end_time: f64,
+    #[serde(default)]
+    pub title: String,
+}
+
+/// Metadata of a downloaded file, read from the info JSON of yt-dlp.
+#[serde_as]
+#[derive(Clone, Debug, Default, Deserialize, Serialize, TS)]
+#[ts(export, export_to = "backend.d.ts")]
+pub struct SourceMetadata {
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub uploader: Option<String>,
+    /// Upload date as `YYYYMMDD`.
+    #[serde(default)]
+    pub upload_date: Option<String>,
+    #[serde_as(as = "DefaultOnNull")]
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde_as(as = "DefaultOnNull")]
+    #[serde(default)]
+    pub chapters: Vec<Chapter>,
+    /// Local thumbnail image next to the file.
+    #[serde(default)]
+    pub thumbnail: Option<String>,
+    #[serde(default)]
+    pub webpage_url: Option<String>,
+}
+
+impl SourceMetadata {
+    /// Read the `.info.json` file next to a downloaded file, if there is one.
+    pub async fn read(file: &str) -> Option<Self> {
+        let path = Path::new(file);
+        let content = fs::read_to_string(path.with_extension("info.json"))
+            .await
+            .ok()?;
+        let mut metadata: Self = serde_json::from_str(&content).ok()?;
+
+        // The info JSON contains only the URL of the thumbnail.
+        metadata.thumbnail = THUMBNAIL_EXTENSIONS
+            .iter()
+            .map(|ext| path.with_extension(ext))
+            .find(|thumb| thumb.is_file())
+            .map(|thumb| thumb.to_string_lossy().to_string());
+
+        Some(metadata)
+    }
+
+    /// Upload date as `YYYY-MM-DD`.
+    fn date(&self) -> Option<String> {
+        let date = self.upload_date.as_deref()?;
+
+        (date.len() == 8 && date.chars().all(|c| c.is_ascii_digit()))
+            .then(|| format!("{}-{}-{}", &date[..4], &date[4..6], &date[6..]))
+    }
+
+    /// ffmpeg output metadata arguments.
+    pub fn args(&self) -> Vec<String> {
+        let mut args = vec![];
+        let values = [
+            ("title", self.title.clone()),
+            ("artist", self.uploader.clone()),
+            ("description", self.description.clone()),
+            ("date", self.date()),
+        ];
+
+        for (key, value) in values {
+            if let Some(value) = value.filter(|v| !v.trim().is_empty()) {
+                args.extend(vec_strings!["-metadata", format!("{key}={value}")]);
+            }
+        }
+
+        args
+    }
+
+    /// Chapters as FFMETADATA file content.
+    ///
+    /// Only chapters between `start` and `end` (0 for the full length) are kept,
+    /// they are moved to `start` and shifted by `offset`, the length of an intro.
+    pub fn ffmetadata(&self, start: f64, end: f64, offset: f64) -> Option<String> {
+        let mut content = ";FFMETADATA1\n".to_string();
+        let mut count = 0;
+
+        for chapter in &self.chapters {
+            let chapter_end = if end > 0.0 {
+                chapter.end_time.min(end)
+            } else {
+                chapter.end_time
+            };
+            let chapter_start = chapter.start_time.max(start);
+
+            if chapter_end <= chapter_start {
+                continue;
+            }
+
+            content.push_str(&format!(
+                "[CHAPTER]\nTIMEBASE=1/1000\nSTART={}\nEND={}\ntitle={}\n",
+                ((chapter_start - start + offset) * 1000.0).round() as i64,
+                ((chapter_end - start + offset) * 1000.0).round() as i64,
+                escape(&chapter.title)
+            ));
+            count += 1;
+        }
+
+        (count > 0).then_some(content)
+    }
+}
+
+impl Publish {
+    /// Fill empty fields with the metadata of the source.
+    pub fn fill(&mut self, metadata: &SourceMetadata) {
+        let fields = [
+            (&mut self.name, metadata.title.clone()),
+            (&mut self.description, metadata.description.clone()),
+            (&mut self.tags, Some(metadata.tags.join(","))),
+            (&mut self.thumbnail, metadata.thumbnail.clone()),
+        ];
+
+        for (field, value) in fields {
+            if field.trim().is_empty()
+                && let Some(value) = value
+            {
+                *field = value;
+            }
+        }
+    }
+}
+
+/// Escape special characters of FFMETADATA values.
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+
+    for c in value.chars() {
+        if matches!(c, '=' | ';' | '#' | '\\' | '\n') {
+            escaped.push('\\');
+        }
+
+        escaped.push(c);
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_info_json() {
+        let metadata: SourceMetadata = serde_json::from_str(
+            r#"{"title": "Talk", "upload_date": "20240131", "tags": null, "chapters": null, "duration": 60}"#,
+        )
+        .unwrap();
+
+        assert!(metadata.tags.is_empty());
+        assert!(metadata.chapters.is_empty());
+        assert_eq!(
+            metadata.args(),
+            ["-metadata", "title=Talk", "-metadata", "date=2024-01-31"]
+        );
+    }
+
+    #[test]
+    fn trims_and_shifts_chapters() {
+        let chapter = |start_time, end_time, title: &str| Chapter {
+            start_time,
+            end_time,
+            title: title.to_string(),
+        };
+        let metadata = SourceMetadata {
+            chapters: vec![
+                chapter(0.0, 10.0, "Intro"),
+                chapter(10.0, 20.0, "Q=A; end"),
+                chapter(20.0, 30.0, "Outro"),
+            ],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            metadata.ffmetadata(5.0, 20.0, 2.0).unwrap(),
+            ";FFMETADATA1\n\
+             [CHAPTER]\nTIMEBASE=1/1000\nSTART=2000\nEND=7000\ntitle=Intro\n\
+             [CHAPTER]\nTIMEBASE=1/1000\nSTART=7000\nEND=17000\ntitle=Q\\=A\\; end\n"
+        );
+        assert_eq!(metadata.ffmetadata(30.0, 0.0, 0.0), None);
+    }
+}
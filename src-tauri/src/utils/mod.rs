@@ -10,10 +10,17 @@ use tauri_plugin_updater::UpdaterExt;
 use tokio::fs::{self};
 use tokio_stream::StreamExt;
 
+pub mod backup;
+pub mod config;
 pub mod download;
+pub mod download_queue;
 pub mod errors;
+pub mod http_download;
 pub mod logging;
+pub mod metadata;
 pub mod presets;
+pub mod rules;
+pub mod schema;
 pub mod template;
 
 use crate::ProcessError;
@@ -148,6 +155,12 @@ pub async fn copy_assets(app: &AppHandle) -> Result<(), ProcessError> {
             Some(Ok(entry)) => {
                 let source_path = entry.path();
                 let relative_path = source_path.strip_prefix(&path_source)?;
+
+                // Bundled presets are read directly from the resources.
+                if relative_path.starts_with("presets") {
+                    continue;
+                }
+
                 let target_path = target_source.join(relative_path);
 
                 if source_path.is_dir() && !target_path.is_dir() {
@@ -164,6 +177,8 @@ pub async fn copy_assets(app: &AppHandle) -> Result<(), ProcessError> {
         }
     }
 
+    presets::remove_bundled_copies(&Some(app.clone())).await?;
+
     Ok(())
 }
 
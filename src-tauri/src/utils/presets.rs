@@ -1,23 +1,54 @@
 use std::{
-    env,
+    collections::HashMap,
+    env, fmt,
+    io::{Cursor, Read, Write},
     path::{Path, PathBuf},
-    sync::{Arc, atomic::AtomicBool},
+    sync::{Arc, OnceLock, atomic::AtomicBool},
 };
 
+use log::*;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use serde_with::serde_as;
-use tauri::{AppHandle, Manager};
+use sha2::{Digest, Sha256};
+use shlex::split;
+use tauri::{AppHandle, Manager, path::BaseDirectory};
 use tokio::{fs, io::AsyncWriteExt};
 use ts_rs::TS;
+use zip::write::SimpleFileOptions;
 
-use crate::{ProcessError, cli::IDENTIFIER};
+use crate::{
+    Config, ProcessError,
+    cli::IDENTIFIER,
+    utils::{
+        backup::data_path,
+        rules::PresetRule,
+        schema::{Schema, validate},
+    },
+};
+
+/// SHA-256 of bundled presets from before `extends`, older versions copied them
+/// into the user folder.
+const LEGACY_BUNDLED_HASHES: [&str; 2] = [
+    // youtube_4k.json
+    "8ed9d0a28bd169e9197a29588a741a98315d3036a856434f5be6722b961ba8d5",
+    // youtube_HD_nvenc.json
+    "d1d38bce9f93175fe9f347b11ddd3733ae51706ba4065a49f9d64a1e72b0e5a2",
+];
+/// Written after the copies of bundled presets are removed from the user folder.
+const BUNDLED_CLEANUP_MARKER: &str = ".bundled-presets-cleaned";
+
+/// User preset folder of the CLI, from `--presets-dir`.
+static CLI_PRESET_PATH: OnceLock<PathBuf> = OnceLock::new();
 
 #[serde_as]
 #[derive(Clone, Debug, Default, Deserialize, Serialize, TS)]
 #[ts(export, export_to = "backend.d.ts")]
 pub struct Preset {
     pub name: String,
+    /// Name of a base preset, its fields are used where this preset doesn't set them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extends: Option<String>,
     pub title: String,
     pub tooltip: String,
     pub input: Option<String>,
@@ -33,6 +64,15 @@ pub struct Preset {
     #[ts(type = "string")]
     #[serde(default)]
     pub container_audio: Option<String>,
+    /// Rules evaluated against the source, to skip or adapt the preset.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rules: Vec<PresetRule>,
+    /// Names of the publish targets, which receive the output.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub publish_targets: Vec<String>,
+    /// Directory layer the preset was read from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<PresetSource>,
     #[ts(type = "string")]
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub output_path: Option<PathBuf>,
@@ -41,10 +81,38 @@ pub struct Preset {
     pub finished: Arc<AtomicBool>,
 }
 
+/// Preset directories in ascending precedence, a preset in a later layer
+/// replaces a preset with the same name from an earlier one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize, TS)]
+#[ts(export, export_to = "backend.d.ts")]
+#[serde(rename_all = "lowercase")]
+pub enum PresetSource {
+    /// Shipped with the application, read-only.
+    Bundled,
+    /// Shared folder from the config, for example on a network drive.
+    Shared,
+    /// Presets of the user, managed in the app.
+    User,
+    /// `.adconverter/presets` next to the source media.
+    Project,
+}
+
+impl fmt::Display for PresetSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Bundled => f.pad("bundled"),
+            Self::Shared => f.pad("shared"),
+            Self::User => f.pad("user"),
+            Self::Project => f.pad("project"),
+        }
+    }
+}
+
 impl Preset {
     pub async fn save(&mut self, path: &Path) -> Result<(), ProcessError> {
         let preset_path = path.join(format!("{}.json", self.name));
         self.output_path = None;
+        self.source = None;
 
         let json = serde_json::to_string_pretty(&self)?;
         let mut file = fs::File::create(preset_path).await?;
@@ -54,7 +122,271 @@ impl Preset {
     }
 }
 
+/// One ffmpeg option or filter from a preset field: the key and its values.
+pub type Entry = (String, Vec<String>);
+
+fn value_string(value: &Value) -> Option<String> {
+    match value {
+        Value::Null => None,
+        Value::String(s) => Some(s.clone()),
+        _ => Some(value.to_string()),
+    }
+}
+
+/// Read a preset field in object form `{"-c:v": "libx264"}` or in ordered
+/// list form `[["-map", "0:v"], "-map 0:a"]`. The list form allows repeated keys
+/// and keeps the order; raw strings are split by `split_raw`.
+fn entries(value: &Value, split_raw: fn(&str) -> Option<Entry>) -> Vec<Entry> {
+    match value {
+        Value::Object(map) => map
+            .iter()
+            .map(|(key, val)| (key.clone(), value_string(val).into_iter().collect()))
+            .collect(),
+        Value::Array(list) => list
+            .iter()
+            .filter_map(|item| match item {
+                Value::Array(pair) => {
+                    let key = value_string(pair.first()?)?;
+                    let values = pair.iter().skip(1).filter_map(value_string).collect();
+
+                    Some((key, values))
+                }
+                Value::String(raw) => split_raw(raw),
+                _ => None,
+            })
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// Entries of `video`/`audio`, raw strings like `"-metadata:s:a:0 language=ger"`
+/// are split shell like.
+pub fn arg_entries(value: &Value) -> Vec<Entry> {
+    entries(value, |raw| {
+        let mut args = split(raw)?;
+
+        if args.is_empty() {
+            return None;
+        }
+
+        let key = args.remove(0);
+
+        Some((key, args))
+    })
+}
+
+/// Entries of `filter_video`/`filter_audio`, raw strings like `"scale=1920:1080"`
+/// are split at the first `=`.
+pub fn filter_entries(value: &Value) -> Vec<Entry> {
+    entries(value, |raw| {
+        let raw = raw.trim();
+
+        if raw.is_empty() {
+            return None;
+        }
+
+        match raw.split_once('=') {
+            Some((key, val)) => Some((key.to_string(), vec![val.to_string()])),
+            None => Some((raw.to_string(), vec![])),
+        }
+    })
+}
+
+/// First value of the last entry with `key`, like ffmpeg the last option wins.
+pub fn entry_value<'a>(entries: &'a [Entry], key: &str) -> Option<&'a str> {
+    entries
+        .iter()
+        .rev()
+        .find(|(k, _)| k == key)
+        .and_then(|(_, values)| values.first())
+        .map(String::as_str)
+}
+
+/// Fields which are merged key by key with the base preset,
+/// a `null` value removes the key from the base, in list form a `[key, null]` entry.
+const MERGED_FIELDS: [&str; 4] = ["filter_video", "filter_audio", "video", "audio"];
+
+/// Entries of a merged field, `None` marks a key to remove.
+fn marked_entries(field: &str, value: &Value) -> Vec<(String, Option<Vec<String>>)> {
+    let parse = if field.starts_with("filter_") {
+        filter_entries
+    } else {
+        arg_entries
+    };
+    // Object entries become `[key, value]` pairs of the list form.
+    let items = match value {
+        Value::Object(map) => map
+            .iter()
+            .map(|(key, val)| Value::Array(vec![Value::String(key.clone()), val.clone()]))
+            .collect(),
+        Value::Array(list) => list.clone(),
+        _ => vec![],
+    };
+
+    items
+        .iter()
+        .flat_map(|item| {
+            let removed =
+                matches!(item, Value::Array(pair) if pair.len() == 2 && pair[1].is_null());
+
+            parse(&Value::Array(vec![item.clone()]))
+                .into_iter()
+                .map(move |(key, values)| (key, (!removed).then_some(values)))
+        })
+        .collect()
+}
+
+/// Merge a field, where the base or the child is in list form, key by key.
+///
+/// Child entries replace all base entries of their key at the place of the first one,
+/// the order and repeated keys stay. The result is in list form.
+fn merge_entries(field: &str, base: &Value, child: &Value) -> Value {
+    let mut merged: Vec<Entry> = marked_entries(field, base)
+        .into_iter()
+        .filter_map(|(key, values)| Some((key, values?)))
+        .collect();
+    let child = marked_entries(field, child);
+    let mut keys: Vec<&str> = vec![];
+
+    for (key, _) in &child {
+        if !keys.contains(&key.as_str()) {
+            keys.push(key);
+        }
+    }
+
+    for key in keys {
+        let position = merged.iter().position(|(k, _)| k == key);
+        let entries = child
+            .iter()
+            .filter(|(k, _)| k == key)
+            .filter_map(|(k, values)| Some((k.clone(), values.clone()?)));
+
+        merged.retain(|(k, _)| k != key);
+
+        match position {
+            Some(index) => {
+                merged.splice(index..index, entries);
+            }
+            None => merged.extend(entries),
+        }
+    }
+
+    Value::Array(
+        merged
+            .into_iter()
+            .map(|(key, values)| {
+                Value::Array(
+                    std::iter::once(key)
+                        .chain(values)
+                        .map(Value::String)
+                        .collect(),
+                )
+            })
+            .collect(),
+    )
+}
+
+pub fn merge_preset(base: &Value, child: &Value) -> Value {
+    let (Value::Object(base), Value::Object(child)) = (base, child) else {
+        return child.clone();
+    };
+
+    let mut merged = base.clone();
+
+    for (field, value) in child {
+        match (merged.get_mut(field), value) {
+            (Some(Value::Object(base_map)), Value::Object(child_map))
+                if MERGED_FIELDS.contains(&field.as_str()) =>
+            {
+                for (key, val) in child_map {
+                    if val.is_null() {
+                        base_map.remove(key);
+                    } else {
+                        base_map.insert(key.clone(), val.clone());
+                    }
+                }
+            }
+            (Some(base_value), Value::Object(_) | Value::Array(_))
+                if MERGED_FIELDS.contains(&field.as_str())
+                    && (base_value.is_array() || value.is_array())
+                    && (base_value.is_object() || base_value.is_array()) =>
+            {
+                *base_value = merge_entries(field, base_value, value);
+            }
+            _ => {
+                merged.insert(field.clone(), value.clone());
+            }
+        }
+    }
+
+    Value::Object(merged)
+}
+
+fn resolve_preset(
+    name: &str,
+    raw: &HashMap<String, Value>,
+    resolved: &mut HashMap<String, Value>,
+    stack: &mut Vec<String>,
+) -> Result<Value, ProcessError> {
+    if let Some(value) = resolved.get(name) {
+        return Ok(value.clone());
+    }
+
+    if stack.iter().any(|n| n == name) {
+        stack.push(name.to_string());
+
+        return Err(ProcessError::Custom(format!(
+            "Preset inheritance cycle: {}",
+            stack.join(" -> ")
+        )));
+    }
+
+    let value = raw
+        .get(name)
+        .ok_or_else(|| ProcessError::Custom(format!("Preset \"{name}\" not found")))?;
+
+    let value = match value.get("extends").and_then(Value::as_str) {
+        Some(base_name) => {
+            if !raw.contains_key(base_name) {
+                return Err(ProcessError::Custom(format!(
+                    "Preset \"{name}\" extends unknown preset \"{base_name}\""
+                )));
+            }
+
+            stack.push(name.to_string());
+            let base = resolve_preset(base_name, raw, resolved, stack)?;
+            stack.pop();
+
+            merge_preset(&base, value)
+        }
+        None => value.clone(),
+    };
+
+    resolved.insert(name.to_string(), value.clone());
+
+    Ok(value)
+}
+
+/// Resolve the `extends` chains of raw preset JSON values, keyed by preset name.
+pub fn resolve_extends(raw: &HashMap<String, Value>) -> Vec<(String, Result<Value, ProcessError>)> {
+    let mut resolved = HashMap::new();
+
+    raw.keys()
+        .map(|name| {
+            let value = resolve_preset(name, raw, &mut resolved, &mut vec![]);
+
+            (name.clone(), value)
+        })
+        .collect()
+}
+
 pub fn preset_path(app: &Option<AppHandle>) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    if app.is_none()
+        && let Some(directory) = CLI_PRESET_PATH.get()
+    {
+        return Ok(directory.clone());
+    }
+
     let mut directory = if cfg!(debug_assertions) {
         env::current_dir()?.join("assets")
     } else if let Some(a) = app {
@@ -70,30 +402,677 @@ pub fn preset_path(app: &Option<AppHandle>) -> Result<PathBuf, Box<dyn std::erro
     Ok(directory)
 }
 
+/// Replace the user preset folder of the CLI, can be set only once.
+pub fn set_cli_preset_path(directory: PathBuf) {
+    if CLI_PRESET_PATH.set(directory).is_err() {
+        warn!("User preset folder is already set");
+    }
+}
+
+/// Presets shipped with the application.
+pub fn bundled_preset_path(app: &Option<AppHandle>) -> Option<PathBuf> {
+    if cfg!(debug_assertions) {
+        return env::current_dir()
+            .ok()
+            .map(|d| d.join("assets").join("presets"));
+    }
+
+    match app {
+        Some(a) => a
+            .path()
+            .resolve("assets/presets", BaseDirectory::Resource)
+            .ok(),
+        None => {
+            let exe_dir = env::current_exe().ok()?.parent()?.to_path_buf();
+            let mut candidates = vec![exe_dir.join("assets").join("presets")];
+
+            if cfg!(target_os = "linux") {
+                candidates.push(PathBuf::from("/usr/lib/adConverter/assets/presets"));
+            } else if cfg!(target_os = "macos") {
+                candidates.push(exe_dir.join("../Resources/assets/presets"));
+            }
+
+            candidates.into_iter().find(|p| p.is_dir())
+        }
+    }
+}
+
+/// Project presets in `.adconverter/presets` next to the source media.
+pub fn project_preset_path(media: &Path) -> Option<PathBuf> {
+    let path = media.parent()?.join(".adconverter").join("presets");
+
+    path.is_dir().then_some(path)
+}
+
+/// All preset directories in ascending precedence. A directory is only
+/// listed once, in its highest layer.
+pub fn preset_dirs(
+    app: &Option<AppHandle>,
+    config: &Config,
+    media: Option<&Path>,
+) -> Result<Vec<(PresetSource, PathBuf)>, Box<dyn std::error::Error>> {
+    let layers = [
+        (PresetSource::Bundled, bundled_preset_path(app)),
+        (PresetSource::Shared, config.shared_preset_path.clone()),
+        (PresetSource::User, Some(preset_path(app)?)),
+        (PresetSource::Project, media.and_then(project_preset_path)),
+    ];
+    let mut dirs: Vec<(PresetSource, PathBuf)> = vec![];
+
+    for (source, path) in layers {
+        if let Some(path) = path {
+            dirs.retain(|(_, p)| p != &path);
+            dirs.push((source, path));
+        }
+    }
+
+    Ok(dirs)
+}
+
+/// Read the presets of all layers, broken files are skipped with a warning.
+/// Project presets are included when `media` is given.
 pub async fn collect_presets(
     app: &Option<AppHandle>,
+    config: &Config,
+    media: Option<&Path>,
 ) -> Result<Vec<Preset>, Box<dyn std::error::Error>> {
-    let path = preset_path(app)?;
-    let mut entries = fs::read_dir(path).await?;
+    let mut raw = HashMap::new();
+    let mut files: HashMap<String, (PresetSource, PathBuf)> = HashMap::new();
     let mut presets = vec![];
 
+    for (source, path) in preset_dirs(app, config, media)? {
+        if !path.is_dir() {
+            if source != PresetSource::User {
+                warn!("Preset folder {path:?} not found");
+            }
+            continue;
+        }
+
+        let mut entries = fs::read_dir(&path).await?;
+        let mut layer = HashMap::new();
+
+        while let Some(entry) = entries.next_entry().await? {
+            let extension = entry
+                .path()
+                .extension()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_lowercase();
+
+            if extension == "json" {
+                let contents = fs::read_to_string(entry.path()).await?;
+                let value = match validate(Schema::Preset, &entry.path(), &contents) {
+                    Ok(value) => value,
+                    Err(e) => {
+                        warn!("Skip preset: {e}");
+                        continue;
+                    }
+                };
+                let name = value
+                    .get("name")
+                    .and_then(Value::as_str)
+                    .map(str::to_string)
+                    .unwrap_or_default();
+
+                if let Some(other) = layer.insert(name.clone(), entry.path()) {
+                    warn!(
+                        "Preset name \"{name}\" is used in {other:?} and {:?}",
+                        entry.path()
+                    );
+                }
+
+                if let Some((other_source, other)) =
+                    files.insert(name.clone(), (source, entry.path()))
+                    && other_source != source
+                {
+                    debug!(
+                        "Preset \"{name}\" from {other:?} is overridden by {:?}",
+                        entry.path()
+                    );
+                }
+
+                raw.insert(name, value);
+            }
+        }
+    }
+
+    for (name, value) in resolve_extends(&raw) {
+        let Some((source, file)) = files.get(&name).cloned() else {
+            continue;
+        };
+
+        match value.and_then(|v| serde_json::from_value::<Preset>(v).map_err(ProcessError::from)) {
+            Ok(mut preset) => {
+                preset.source = Some(source);
+                presets.push(preset);
+            }
+            Err(e) => warn!("Skip preset {file:?}: {e}"),
+        }
+    }
+
+    presets.sort_by_key(|a| a.name.to_lowercase());
+
+    Ok(presets)
+}
+
+async fn read_raw(path: &Path) -> Result<Value, ProcessError> {
+    let contents = fs::read_to_string(path).await?;
+
+    validate(Schema::Preset, path, &contents)
+}
+
+async fn write_raw(directory: &Path, value: &Value) -> Result<PathBuf, ProcessError> {
+    let name = value
+        .get("name")
+        .and_then(Value::as_str)
+        .unwrap_or_default();
+    let path = directory.join(format!("{name}.json"));
+    let json = serde_json::to_string_pretty(value)?;
+
+    fs::create_dir_all(directory).await?;
+    fs::write(&path, json).await?;
+
+    Ok(path)
+}
+
+/// All preset files in `directory` with their raw, unresolved JSON.
+async fn preset_files(directory: &Path) -> Result<Vec<(PathBuf, Value)>, ProcessError> {
+    let mut files = vec![];
+
+    if !directory.is_dir() {
+        return Ok(files);
+    }
+
+    let mut entries = fs::read_dir(directory).await?;
+
     while let Some(entry) = entries.next_entry().await? {
-        let extension = entry
-            .path()
+        let path = entry.path();
+
+        if path
             .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("json"))
+            && let Ok(value) = read_raw(&path).await
+        {
+            files.push((path, value));
+        }
+    }
+
+    Ok(files)
+}
+
+/// Preset files of all layers, a later layer replaces presets with the same name.
+async fn layered_files(
+    app: &Option<AppHandle>,
+    config: &Config,
+) -> Result<Vec<(PathBuf, Value)>, ProcessError> {
+    let mut files: Vec<(PathBuf, Value)> = vec![];
+
+    for (_, directory) in preset_dirs(app, config, None)? {
+        for (path, value) in preset_files(&directory).await? {
+            let name = value.get("name").cloned();
+
+            files.retain(|(_, v)| v.get("name") != name.as_ref());
+            files.push((path, value));
+        }
+    }
+
+    Ok(files)
+}
+
+fn find_in(files: &[(PathBuf, Value)], name: &str) -> Option<(PathBuf, Value)> {
+    files
+        .iter()
+        .find(|(_, value)| value.get("name").and_then(Value::as_str) == Some(name))
+        .cloned()
+}
+
+async fn find_preset(directory: &Path, name: &str) -> Result<(PathBuf, Value), ProcessError> {
+    find_in(&preset_files(directory).await?, name).ok_or_else(|| {
+        ProcessError::Custom(format!(
+            "Preset \"{name}\" not found in user presets {directory:?}"
+        ))
+    })
+}
+
+/// First free name like `name_2`, `name_3` if `name` is taken.
+fn unique_name(taken: &[String], name: &str) -> String {
+    let mut new_name = name.to_string();
+    let mut count = 1;
+
+    while taken.contains(&new_name) {
+        count += 1;
+        new_name = format!("{name}_{count}");
+    }
+
+    new_name
+}
+
+/// Remove unchanged copies of bundled presets from the user folder, runs only once.
+///
+/// Older versions copied all bundled presets there, as user presets they would shadow
+/// the updated bundled ones. Changed copies stay and are reported.
+pub async fn remove_bundled_copies(app: &Option<AppHandle>) -> Result<(), ProcessError> {
+    let marker = data_path(app)?.join(BUNDLED_CLEANUP_MARKER);
+    let user = preset_path(app)?;
+
+    let Some(bundled) = bundled_preset_path(app) else {
+        return Ok(());
+    };
+
+    if marker.is_file() || !user.is_dir() || user == bundled {
+        return Ok(());
+    }
+
+    let mut entries = fs::read_dir(&user).await?;
+
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        let bundled_file = bundled.join(entry.file_name());
+
+        if path.extension().is_none_or(|e| e != "json") || !bundled_file.is_file() {
+            continue;
+        }
+
+        let content = fs::read(&path).await?;
+        let hash = format!("{:x}", Sha256::digest(&content));
+
+        if content == fs::read(&bundled_file).await?
+            || LEGACY_BUNDLED_HASHES.contains(&hash.as_str())
+        {
+            info!("Remove copy of bundled preset {path:?}");
+            fs::remove_file(&path).await?;
+        } else {
+            warn!("User preset {path:?} is a changed copy of a bundled preset and overrides it");
+        }
+    }
+
+    if let Some(parent) = marker.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+
+    fs::write(marker, "").await?;
+
+    Ok(())
+}
+
+fn preset_names(files: &[(PathBuf, Value)]) -> Vec<String> {
+    files
+        .iter()
+        .filter_map(|(_, v)| v.get("name").and_then(Value::as_str).map(str::to_string))
+        .collect()
+}
+
+fn check_name(name: &str) -> Result<(), ProcessError> {
+    if name.trim().is_empty() || name.contains(['/', '\\']) {
+        return Err(ProcessError::Custom(format!(
+            "Invalid preset name: \"{name}\""
+        )));
+    }
+
+    Ok(())
+}
+
+pub async fn delete_preset(app: &Option<AppHandle>, name: &str) -> Result<(), ProcessError> {
+    let directory = preset_path(app)?;
+    let (path, _) = find_preset(&directory, name).await?;
+    let dependents = preset_files(&directory)
+        .await?
+        .into_iter()
+        .filter(|(_, v)| v.get("extends").and_then(Value::as_str) == Some(name))
+        .filter_map(|(_, v)| v.get("name").and_then(Value::as_str).map(str::to_string))
+        .collect::<Vec<_>>();
+
+    if !dependents.is_empty() {
+        return Err(ProcessError::Custom(format!(
+            "Preset \"{name}\" is extended by: {}",
+            dependents.join(", ")
+        )));
+    }
+
+    fs::remove_file(path).await?;
+
+    Ok(())
+}
+
+/// Rename a preset and update all presets which extend it.
+///
+/// The new name can't be taken in any layer, the renamed preset would shadow the other one.
+pub async fn rename_preset(
+    app: &Option<AppHandle>,
+    config: &Config,
+    name: &str,
+    new_name: &str,
+) -> Result<(), ProcessError> {
+    check_name(new_name)?;
+
+    let directory = preset_path(app)?;
+    let files = preset_files(&directory).await?;
+
+    if preset_names(&layered_files(app, config).await?)
+        .iter()
+        .any(|n| n == new_name)
+    {
+        return Err(ProcessError::Custom(format!(
+            "Preset \"{new_name}\" already exists"
+        )));
+    }
+
+    let (path, mut value) = find_preset(&directory, name).await?;
+    value["name"] = Value::String(new_name.to_string());
+
+    write_raw(&directory, &value).await?;
+
+    if path != directory.join(format!("{new_name}.json")) {
+        fs::remove_file(path).await?;
+    }
+
+    for (path, mut value) in files {
+        if value.get("extends").and_then(Value::as_str) == Some(name) {
+            value["extends"] = Value::String(new_name.to_string());
+            let json = serde_json::to_string_pretty(&value)?;
+
+            fs::write(path, json).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Copy a preset from any layer to the user presets, without `new_name`
+/// the copy gets a free name like `name_2`.
+pub async fn duplicate_preset(
+    app: &Option<AppHandle>,
+    config: &Config,
+    name: &str,
+    new_name: Option<&str>,
+) -> Result<String, ProcessError> {
+    let directory = preset_path(app)?;
+    let files = layered_files(app, config).await?;
+    let taken = preset_names(&files);
+    let (_, mut value) = find_in(&files, name)
+        .ok_or_else(|| ProcessError::Custom(format!("Preset \"{name}\" not found")))?;
+
+    let new_name = match new_name {
+        Some(n) if taken.iter().any(|t| t == n) => {
+            return Err(ProcessError::Custom(format!(
+                "Preset \"{n}\" already exists"
+            )));
+        }
+        Some(n) => n.to_string(),
+        None => unique_name(&taken, name),
+    };
+
+    check_name(&new_name)?;
+
+    value["name"] = Value::String(new_name.clone());
+
+    if let Some(title) = value.get("title").and_then(Value::as_str) {
+        value["title"] = Value::String(format!("{title} (copy)"));
+    }
+
+    write_raw(&directory, &value).await?;
+
+    Ok(new_name)
+}
+
+/// Import presets from a JSON file or a zip archive with JSON files.
+/// Existing presets of all layers are replaced or shadowed with `overwrite`,
+/// otherwise the imported one gets a free name.
+/// Returns the names of the imported presets.
+pub async fn import_presets(
+    app: &Option<AppHandle>,
+    config: &Config,
+    source: &Path,
+    overwrite: bool,
+) -> Result<Vec<String>, ProcessError> {
+    let directory = preset_path(app)?;
+    let mut taken = preset_names(&layered_files(app, config).await?);
+    let mut imported = vec![];
+    let mut values = vec![];
+
+    if source
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("zip"))
+    {
+        let bytes = fs::read(source).await?;
+        let mut archive = zip::ZipArchive::new(Cursor::new(bytes))?;
+
+        for i in 0..archive.len() {
+            let mut file = archive.by_index(i)?;
+            let Some(name) = file.enclosed_name() else {
+                continue;
+            };
+
+            if !file.is_file() || !name.extension().is_some_and(|e| e == "json") {
+                continue;
+            }
+
+            let mut contents = String::new();
+            file.read_to_string(&mut contents)?;
+
+            values.push(validate(Schema::Preset, &source.join(name), &contents)?);
+        }
+    } else {
+        values.push(read_raw(source).await?);
+    }
+
+    for mut value in values {
+        let name = value
+            .get("name")
+            .and_then(Value::as_str)
             .unwrap_or_default()
-            .to_string_lossy()
-            .to_lowercase();
+            .to_string();
+
+        check_name(&name)?;
 
-        if extension == "json" {
-            let contents = fs::read_to_string(entry.path()).await?;
-            let preset: Preset = serde_json::from_str(&contents)?;
+        if !overwrite && taken.contains(&name) {
+            let new_name = unique_name(&taken, &name);
+            warn!("Preset \"{name}\" exists, import it as \"{new_name}\"");
 
-            presets.push(preset);
+            value["name"] = Value::String(new_name);
+        } else if overwrite && let Ok((path, _)) = find_preset(&directory, &name).await {
+            fs::remove_file(path).await?;
         }
+
+        let name = value["name"].as_str().unwrap_or_default().to_string();
+
+        write_raw(&directory, &value).await?;
+        taken.push(name.clone());
+        imported.push(name);
     }
 
-    presets.sort_by_key(|a| a.name.to_lowercase());
+    Ok(imported)
+}
 
-    Ok(presets)
+/// Export presets from all layers, one preset to a JSON file, several presets
+/// or a target with `.zip` extension to a zip archive.
+pub async fn export_presets(
+    app: &Option<AppHandle>,
+    config: &Config,
+    names: &[String],
+    target: &Path,
+) -> Result<(), ProcessError> {
+    let is_zip = target
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("zip"));
+
+    if names.is_empty() {
+        return Err(ProcessError::Custom(
+            "No presets selected for export".into(),
+        ));
+    }
+
+    if names.len() > 1 && !is_zip {
+        return Err(ProcessError::Custom(format!(
+            "Several presets can only be exported to a .zip file, not {target:?}"
+        )));
+    }
+
+    let files = layered_files(app, config).await?;
+    let mut values = vec![];
+
+    for name in names {
+        let (_, value) = find_in(&files, name)
+            .ok_or_else(|| ProcessError::Custom(format!("Preset \"{name}\" not found")))?;
+
+        values.push(value);
+    }
+
+    if !is_zip {
+        fs::write(target, serde_json::to_string_pretty(&values[0])?).await?;
+
+        return Ok(());
+    }
+
+    let mut archive = zip::ZipWriter::new(Cursor::new(Vec::new()));
+
+    for value in values {
+        let name = value["name"].as_str().unwrap_or_default();
+
+        archive.start_file(format!("{name}.json"), SimpleFileOptions::default())?;
+        archive.write_all(serde_json::to_string_pretty(&value)?.as_bytes())?;
+    }
+
+    let buffer = archive.finish()?.into_inner();
+    fs::write(target, buffer).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn raw(presets: Vec<Value>) -> HashMap<String, Value> {
+        presets
+            .into_iter()
+            .map(|p| (p["name"].as_str().unwrap().to_string(), p))
+            .collect()
+    }
+
+    #[test]
+    fn extends_overrides_and_removes_keys() {
+        let presets = raw(vec![
+            json!({
+                "name": "base",
+                "title": "Base",
+                "video": { "-c:v": "libx264", "-crf": 21 },
+                "container_video": "mp4"
+            }),
+            json!({
+                "name": "child",
+                "extends": "base",
+                "title": "Child",
+                "video": { "-c:v": "h264_nvenc", "-crf": null },
+                "container_video": "mkv"
+            }),
+        ]);
+        let mut resolved = HashMap::new();
+        let child = resolve_preset("child", &presets, &mut resolved, &mut vec![]).unwrap();
+
+        assert_eq!(child["name"], "child");
+        assert_eq!(child["title"], "Child");
+        assert_eq!(child["video"], json!({ "-c:v": "h264_nvenc" }));
+        assert_eq!(child["container_video"], "mkv");
+    }
+
+    #[test]
+    fn extends_merges_list_form_by_key() {
+        let base = json!({
+            "name": "base",
+            "video": [["-map", "0:v"], ["-map", "0:a"], "-c:v libx264", ["-crf", 21], ["-an"]],
+            "filter_video": ["yadif", "scale=1920:1080"]
+        });
+        let child = json!({
+            "name": "child",
+            "video": [["-c:v", "h264_nvenc"], ["-crf", null], ["-preset", "p5"]],
+            "filter_video": { "scale": "1280:720", "yadif": null }
+        });
+        let merged = merge_preset(&base, &child);
+
+        assert_eq!(
+            merged["video"],
+            json!([
+                ["-map", "0:v"],
+                ["-map", "0:a"],
+                ["-c:v", "h264_nvenc"],
+                ["-an"],
+                ["-preset", "p5"]
+            ])
+        );
+        assert_eq!(merged["filter_video"], json!([["scale", "1280:720"]]));
+
+        // Repeated keys of the child replace all base entries of the key.
+        let child = json!({ "video": [["-map", "0:v:0"], ["-map", "0:a:1"]] });
+        let merged = merge_preset(&base, &child);
+
+        assert_eq!(
+            arg_entries(&merged["video"])
+                .iter()
+                .filter(|(key, _)| key == "-map")
+                .map(|(_, values)| values[0].as_str())
+                .collect::<Vec<_>>(),
+            ["0:v:0", "0:a:1"]
+        );
+    }
+
+    #[test]
+    fn detects_inheritance_cycles() {
+        let presets = raw(vec![
+            json!({ "name": "a", "extends": "b" }),
+            json!({ "name": "b", "extends": "a" }),
+        ]);
+        let error = resolve_preset("a", &presets, &mut HashMap::new(), &mut vec![]).unwrap_err();
+
+        assert_eq!(error.to_string(), "Preset inheritance cycle: a -> b -> a");
+    }
+
+    #[test]
+    fn reports_unknown_base() {
+        let presets = raw(vec![json!({ "name": "a", "extends": "missing" })]);
+        let (_, value) = resolve_extends(&presets).remove(0);
+        let error = value.unwrap_err();
+
+        assert!(error.to_string().contains("unknown preset \"missing\""));
+    }
+
+    #[test]
+    fn finds_a_free_name() {
+        let taken = vec!["a".to_string(), "a_2".to_string()];
+
+        assert_eq!(unique_name(&taken, "b"), "b");
+        assert_eq!(unique_name(&taken, "a"), "a_3");
+    }
+
+    #[test]
+    fn layers_in_precedence_order() {
+        let config = Config {
+            shared_preset_path: Some(PathBuf::from("/mnt/presets")),
+            ..Default::default()
+        };
+        let dirs = preset_dirs(&None, &config, None).unwrap();
+        let sources = dirs.iter().map(|(s, _)| *s).collect::<Vec<_>>();
+
+        assert!(sources.ends_with(&[PresetSource::Shared, PresetSource::User]));
+        assert!(sources.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_export_targets() {
+        let config = Config::default();
+        let names = vec!["a".to_string(), "b".to_string()];
+
+        assert!(
+            export_presets(&None, &config, &[], Path::new("out.zip"))
+                .await
+                .is_err()
+        );
+        assert!(
+            export_presets(&None, &config, &names, Path::new("out.json"))
+                .await
+                .is_err()
+        );
+    }
 }
@@ -0,0 +1,242 @@
+use std::fmt;
+
+use log::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use ts_rs::TS;
+
+use crate::{Preset, ffmpeg::probe::MediaProbe, utils::presets::merge_preset};
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, TS)]
+#[ts(export, export_to = "backend.d.ts")]
+#[serde(rename_all = "lowercase")]
+pub enum Orientation {
+    Landscape,
+    Portrait,
+    Square,
+}
+
+/// Source properties a rule matches on, all given fields must match.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, TS)]
+#[ts(export, export_to = "backend.d.ts")]
+pub struct RuleCondition {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub width_below: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub width_above: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub height_below: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub height_above: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_below: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_above: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub has_audio: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub has_video: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub orientation: Option<Orientation>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq, TS)]
+#[ts(export, export_to = "backend.d.ts")]
+#[serde(rename_all = "lowercase")]
+pub enum RuleSkip {
+    Skip,
+}
+
+/// What to do when the condition matches: skip the preset
+/// or override preset fields, merged like `extends`.
+#[derive(Clone, Debug, Deserialize, Serialize, TS)]
+#[ts(export, export_to = "backend.d.ts")]
+#[serde(untagged)]
+pub enum RuleAction {
+    Skip(RuleSkip),
+    Override(Value),
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, TS)]
+#[ts(export, export_to = "backend.d.ts")]
+pub struct PresetRule {
+    #[serde(rename = "if")]
+    pub condition: RuleCondition,
+    #[serde(rename = "then")]
+    pub action: RuleAction,
+}
+
+impl RuleCondition {
+    pub fn matches(&self, probe: &MediaProbe) -> bool {
+        let video = probe.video.first();
+        let width = video.and_then(|v| v.width);
+        let height = video.and_then(|v| v.height);
+        let duration = probe.format.duration.unwrap_or_default();
+        let orientation = width.zip(height).map(|(w, h)| match w.cmp(&h) {
+            std::cmp::Ordering::Greater => Orientation::Landscape,
+            std::cmp::Ordering::Less => Orientation::Portrait,
+            std::cmp::Ordering::Equal => Orientation::Square,
+        });
+
+        self.width_below
+            .is_none_or(|v| width.is_some_and(|w| w < v))
+            && self
+                .width_above
+                .is_none_or(|v| width.is_some_and(|w| w > v))
+            && self
+                .height_below
+                .is_none_or(|v| height.is_some_and(|h| h < v))
+            && self
+                .height_above
+                .is_none_or(|v| height.is_some_and(|h| h > v))
+            && self.duration_below.is_none_or(|v| duration < v)
+            && self.duration_above.is_none_or(|v| duration > v)
+            && self.has_audio.is_none_or(|v| v == !probe.audio.is_empty())
+            && self.has_video.is_none_or(|v| v == !probe.video.is_empty())
+            && self.orientation.is_none_or(|v| orientation == Some(v))
+    }
+}
+
+impl fmt::Display for RuleCondition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut parts = vec![];
+
+        if let Some(v) = self.width_below {
+            parts.push(format!("width < {v}"));
+        }
+        if let Some(v) = self.width_above {
+            parts.push(format!("width > {v}"));
+        }
+        if let Some(v) = self.height_below {
+            parts.push(format!("height < {v}"));
+        }
+        if let Some(v) = self.height_above {
+            parts.push(format!("height > {v}"));
+        }
+        if let Some(v) = self.duration_below {
+            parts.push(format!("duration < {v}s"));
+        }
+        if let Some(v) = self.duration_above {
+            parts.push(format!("duration > {v}s"));
+        }
+        if let Some(v) = self.has_audio {
+            parts.push(if v { "has audio" } else { "no audio" }.to_string());
+        }
+        if let Some(v) = self.has_video {
+            parts.push(if v { "has video" } else { "no video" }.to_string());
+        }
+        if let Some(v) = self.orientation {
+            parts.push(format!("{v:?}").to_lowercase());
+        }
+
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+impl Preset {
+    /// Evaluate the preset rules against the source probe.
+    /// Returns `None` when a rule skips the preset, otherwise the adapted preset.
+    pub fn apply_rules(&self, probe: &MediaProbe) -> Option<Preset> {
+        let mut preset = self.clone();
+
+        for rule in &self.rules {
+            if !rule.condition.matches(probe) {
+                continue;
+            }
+
+            match &rule.action {
+                RuleAction::Skip(_) => {
+                    warn!("Skip preset \"{}\", source: {}", self.title, rule.condition);
+
+                    return None;
+                }
+                RuleAction::Override(fields) => {
+                    let merged = serde_json::to_value(&preset)
+                        .map(|base| merge_preset(&base, fields))
+                        .and_then(serde_json::from_value::<Preset>);
+
+                    match merged {
+                        Ok(mut adapted) => {
+                            info!(
+                                "Adapt preset \"{}\", source: {}",
+                                self.title, rule.condition
+                            );
+
+                            adapted.name = self.name.clone();
+                            adapted.finished = self.finished.clone();
+                            adapted.output_path = self.output_path.clone();
+                            preset = adapted;
+                        }
+                        Err(e) => error!("Rule of preset \"{}\" is invalid: {e}", self.title),
+                    }
+                }
+            }
+        }
+
+        Some(preset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::ffmpeg::probe::{AudioStream, VideoStream};
+
+    fn probe(width: i64, height: i64, audio: bool) -> MediaProbe {
+        MediaProbe {
+            video: vec![VideoStream {
+                width: Some(width),
+                height: Some(height),
+                ..Default::default()
+            }],
+            audio: if audio {
+                vec![AudioStream::default()]
+            } else {
+                vec![]
+            },
+            ..Default::default()
+        }
+    }
+
+    fn preset(rules: Value) -> Preset {
+        serde_json::from_value(json!({
+            "name": "test",
+            "title": "Test",
+            "tooltip": "",
+            "filter_video": { "scale": "1920:1080" },
+            "filter_audio": {},
+            "video": {},
+            "audio": {},
+            "rules": rules
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn skips_small_sources() {
+        let preset = preset(json!([{ "if": { "height_below": 1080 }, "then": "skip" }]));
+
+        assert!(preset.apply_rules(&probe(1280, 720, true)).is_none());
+        assert!(preset.apply_rules(&probe(3840, 2160, true)).is_some());
+    }
+
+    #[test]
+    fn adapts_portrait_sources() {
+        let preset = preset(json!([{
+            "if": { "orientation": "portrait", "has_audio": true },
+            "then": { "filter_video": { "scale": "1080:1920" } }
+        }]));
+        let adapted = preset.apply_rules(&probe(1080, 1920, true)).unwrap();
+
+        assert_eq!(adapted.filter_video, json!({ "scale": "1080:1920" }));
+        assert_eq!(
+            preset
+                .apply_rules(&probe(1080, 1920, false))
+                .unwrap()
+                .filter_video,
+            json!({ "scale": "1920:1080" })
+        );
+    }
+}
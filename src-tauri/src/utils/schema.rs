@@ -0,0 +1,108 @@
+use std::{path::Path, sync::LazyLock};
+
+use jsonschema::Validator;
+use serde_json::Value;
+
+use crate::ProcessError;
+
+static PRESET_SCHEMA: LazyLock<Validator> =
+    LazyLock::new(|| compile(include_str!("../../assets/schemas/preset.schema.json")));
+static TEMPLATE_SCHEMA: LazyLock<Validator> =
+    LazyLock::new(|| compile(include_str!("../../assets/schemas/template.schema.json")));
+
+#[derive(Clone, Copy, Debug)]
+pub enum Schema {
+    Preset,
+    Template,
+}
+
+fn compile(schema: &str) -> Validator {
+    let schema: Value = serde_json::from_str(schema).expect("Parse bundled schema");
+
+    jsonschema::validator_for(&schema).expect("Compile bundled schema")
+}
+
+/// Line of the field a JSON pointer like `/video/-crf` points to.
+fn pointer_line(contents: &str, pointer: &str) -> usize {
+    let mut pos = 0;
+
+    for segment in pointer.split('/').skip(1) {
+        if segment.parse::<usize>().is_ok() {
+            continue;
+        }
+
+        let key = format!("\"{}\"", segment.replace("~1", "/").replace("~0", "~"));
+
+        if let Some(found) = contents[pos..].find(&key) {
+            pos += found;
+        }
+    }
+
+    contents[..pos].matches('\n').count() + 1
+}
+
+/// Parse `contents` of the JSON file at `path` and validate it against the bundled schema.
+/// Errors contain the file path, line and field.
+pub fn validate(schema: Schema, path: &Path, contents: &str) -> Result<Value, ProcessError> {
+    let value: Value = serde_json::from_str(contents).map_err(|e| {
+        ProcessError::Custom(format!(
+            "{}:{}:{}: {e}",
+            path.display(),
+            e.line(),
+            e.column()
+        ))
+    })?;
+
+    let validator = match schema {
+        Schema::Preset => &PRESET_SCHEMA,
+        Schema::Template => &TEMPLATE_SCHEMA,
+    };
+
+    let errors = validator
+        .iter_errors(&value)
+        .map(|e| {
+            let pointer = e.instance_path.as_str();
+            let field = if pointer.is_empty() { "/" } else { pointer };
+
+            format!(
+                "{}:{}: field \"{field}\": {e}",
+                path.display(),
+                pointer_line(contents, pointer)
+            )
+        })
+        .collect::<Vec<_>>();
+
+    if !errors.is_empty() {
+        return Err(ProcessError::Custom(errors.join("\n")));
+    }
+
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_path_line_and_field() {
+        let contents = "{\n    \"name\": \"test\",\n    \"title\": \"Test\",\n    \"tooltip\": \"\",\n    \"filter_video\": {},\n    \"filter_audio\": {},\n    \"video\": { \"-crf\": [21] },\n    \"audio\": {}\n}";
+        let error = validate(Schema::Preset, Path::new("test.json"), contents).unwrap_err();
+
+        assert!(
+            error.to_string().starts_with("test.json:7: field \"/video"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn reports_syntax_errors_with_line() {
+        let error = validate(
+            Schema::Template,
+            Path::new("t.json"),
+            "{\n  \"intro\": ,\n}",
+        )
+        .unwrap_err();
+
+        assert!(error.to_string().starts_with("t.json:2:"), "{error}");
+    }
+}
@@ -1,12 +1,60 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use serde::{Deserialize, Serialize};
 use serde_json;
 use serde_with::{NoneAsEmptyString, serde_as};
+use tauri::AppHandle;
 use tokio::{fs, io::AsyncWriteExt};
 use ts_rs::TS;
 
-use crate::ProcessError;
+use crate::{
+    ProcessError,
+    utils::{
+        backup::data_path,
+        schema::{Schema, validate},
+    },
+};
+
+/// Folder of the named templates in the application data.
+pub fn template_path(app: &Option<AppHandle>) -> Result<PathBuf, ProcessError> {
+    Ok(data_path(app)?.join("templates"))
+}
+
+fn named_path(app: &Option<AppHandle>, name: &str) -> Result<PathBuf, ProcessError> {
+    if name.trim().is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+        return Err(ProcessError::Custom(format!(
+            "Invalid template name: \"{name}\""
+        )));
+    }
+
+    Ok(template_path(app)?.join(format!("{name}.json")))
+}
+
+/// Names of the stored templates, sorted.
+pub async fn template_names(app: &Option<AppHandle>) -> Result<Vec<String>, ProcessError> {
+    let directory = template_path(app)?;
+    let mut names = vec![];
+
+    if !directory.is_dir() {
+        return Ok(names);
+    }
+
+    let mut entries = fs::read_dir(directory).await?;
+
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+
+        if path.extension().is_some_and(|e| e == "json")
+            && let Some(stem) = path.file_stem()
+        {
+            names.push(stem.to_string_lossy().to_string());
+        }
+    }
+
+    names.sort();
+
+    Ok(names)
+}
 
 #[serde_as]
 #[derive(Clone, Debug, Default, Deserialize, Serialize, TS)]
@@ -39,11 +87,34 @@ pub struct Template {
 impl Template {
     pub async fn new(path: &Path) -> Result<Self, ProcessError> {
         let contents = fs::read_to_string(path).await?;
-        let template: Self = serde_json::from_str(&contents)?;
+        let value = validate(Schema::Template, path, &contents)?;
+        let template: Self = serde_json::from_value(value)?;
 
         Ok(template)
     }
 
+    /// Template from the named templates of the application data.
+    pub async fn named(app: &Option<AppHandle>, name: &str) -> Result<Self, ProcessError> {
+        let path = named_path(app, name)?;
+
+        if !path.is_file() {
+            return Err(ProcessError::Custom(format!(
+                "Template \"{name}\" not found"
+            )));
+        }
+
+        Self::new(&path).await
+    }
+
+    /// Store the template under a name, to use it for other sources and in the settings backup.
+    pub async fn save_named(self, app: &Option<AppHandle>, name: &str) -> Result<(), ProcessError> {
+        let path = named_path(app, name)?;
+
+        fs::create_dir_all(template_path(app)?).await?;
+
+        self.save(&path.to_string_lossy()).await
+    }
+
     pub async fn save(self, path: &str) -> Result<(), ProcessError> {
         let json = serde_json::to_string_pretty(&self)?;
         let mut file = fs::File::create(path).await?;
@@ -6,8 +6,13 @@ export function findTaskById(tasks: Task[], id: string): Task | undefined {
     return tasks.find((task) => task.id === id)
 }
 
+// URL tasks go through the download queue, which sends them to the encoder.
 export function nextPendingTask(tasks: Task[]): Task | undefined {
-    return tasks.find((task) => !task.finished && !task.active)
+    return tasks.find((task) => !task.finished && !task.active && !task.url)
+}
+
+export function pendingDownloads(tasks: Task[]): Task[] {
+    return tasks.filter((task) => !task.finished && !task.active && task.url)
 }
 
 export function hasPendingTasks(tasks: Task[]): boolean {
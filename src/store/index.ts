@@ -32,6 +32,9 @@ export const useStore = defineStore('index', {
             id: '',
             path: '',
             url: null,
+            playlist: false,
+            playlist_items: null,
+            checksum: null,
             in: 0,
             out: 0,
             lufs: false,
@@ -39,10 +42,15 @@ export const useStore = defineStore('index', {
             transcript: 'none',
             presets: [],
             target: null,
+            shared_decode: false,
+            profile: null,
+            publish_results: [],
             active: false,
             finished: false,
         } as Task,
         presets: [] as Preset[],
+        profile: null as string | null,
+        profiles: [] as string[],
         allFade: { value: false },
         allLufs: { value: false },
         allTranscript: 'none',
@@ -104,5 +112,24 @@ export const useStore = defineStore('index', {
                 }
             }, seconds * 1000)
         },
+
+        applyProfile(config: Config) {
+            this.profile = config.profile
+            this.profiles = Object.keys(config.profiles)
+            this.defaultTask.profile = config.profile
+            this.defaultTask.presets = this.presets.filter((p) => config.default_presets.includes(p.name))
+        },
+
+        mergeProjectPresets(presets: Preset[]) {
+            for (const preset of presets.filter((p) => p.source === 'project')) {
+                const index = this.presets.findIndex((p) => p.name === preset.name)
+
+                if (index >= 0) {
+                    this.presets[index] = preset
+                } else {
+                    this.presets.push(preset)
+                }
+            }
+        },
     },
 })
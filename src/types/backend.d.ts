@@ -3,7 +3,63 @@ import type { JsonValue } from "./serde_json/JsonValue";
 
 export type AudioStream = { channels: bigint, codec_name: string | null, duration: number | null, sample_rate: bigint | null, };
 
-export type Config = { copyright: string, ffmpeg_path: string | null, download_path: string | null, download_args: string, yt_dlp_path: string | null, lufs: LufsConfig, transcript_cmd: string, transcript_lang: Array<LangConfig>, publish_preset: string | null, publisher: JsonValue | null, };
+export type BackupEntry = { kind: BackupKind, name: string, status: EntryStatus, };
+
+export type BackupKind = /**
+ * One key of the config store.
+ */
+"config" | "preset" | /**
+ * Named template.
+ */
+"template" | /**
+ * Transcript scripts.
+ */
+"script";
+
+export type BackupManifest = { version: number, app_version: string, created: string, };
+
+export type BackupPreview = { manifest: BackupManifest, entries: Array<BackupEntry>, };
+
+export type Chapter = { start_time: number, end_time: number, title: string, };
+
+export type Config = { version: number, copyright: string, ffmpeg_path: string | null, download_path: string | null, download_args: string, yt_dlp_path: string | null, 
+/**
+ * Shared preset folder, for example on a network drive.
+ */
+shared_preset_path: string | null, lufs: LufsConfig, transcript_cmd: string, transcript_lang: Array<LangConfig>, 
+/**
+ * Preset, which is published to all targets, when presets have no own targets.
+ */
+publish_preset: string | null, 
+/**
+ * Named delivery targets for published presets.
+ */
+publish_targets: { [key in string]?: PublishTarget }, 
+/**
+ * Presets selected for new tasks.
+ */
+default_presets: Array<string>, 
+/**
+ * Active profile.
+ */
+profile: string | null, profiles: { [key in string]?: Profile }, };
+
+export type DownloadError = { id: string, message: string, 
+/**
+ * Cancelled by the user, not failed.
+ */
+cancelled: boolean, };
+
+export type DownloadProgress = { id: string, 
+/**
+ * Playlist item, starts with 1.
+ */
+item: number, count: number, percent: number, };
+
+export type EntryStatus = "new" | "unchanged" | /**
+ * Exists with different content.
+ */
+"conflict";
 
 export type FFmpegProgress = { title: string, fps: number, bitrate: string, total_size: bigint, elapsed_sec: number, elapsed_pct: bigint, speed: number, progress: string, };
 
@@ -17,12 +73,251 @@ export type MediaFormat = { duration: number | null, nb_streams: bigint, size: b
 
 export type MediaProbe = { format: MediaFormat, audio: Array<AudioStream>, video: Array<VideoStream>, };
 
-export type Preset = { name: string, title: string, tooltip: string, input: string | null, lufs?: boolean | null, filter_video: JsonValue, filter_audio: JsonValue, video: JsonValue, audio: JsonValue, container_video: string, container_audio: string, output_path?: string, finished: bool, };
+/**
+ * PeerTube channel, the GUI writes the tokens on login.
+ */
+export type PeerTubeConfig = { url: string, channel_id: bigint, username: string, 
+/**
+ * Only needed without a valid token, for example for logins without the GUI.
+ */
+password?: string | null, client_id: string, client_secret: string, access_token: string, refresh_token: string, 
+/**
+ * Unix time of the last token request.
+ */
+timestamp: number, expires_in: number, refresh_token_expires_in: number, 
+/**
+ * 1 public, 2 unlisted, 3 private, 4 internal.
+ */
+privacy?: number | null, category?: number | null, 
+/**
+ * Language of the videos, also used for captions of auto detected transcripts.
+ */
+language?: string | null, };
+
+export type Orientation = "landscape" | "portrait" | "square";
+
+export type Preset = { name: string, 
+/**
+ * Name of a base preset, its fields are used where this preset doesn't set them.
+ */
+extends?: string | null, title: string, tooltip: string, input: string | null, lufs?: boolean | null, filter_video: JsonValue, filter_audio: JsonValue, video: JsonValue, audio: JsonValue, container_video: string, container_audio: string, 
+/**
+ * Rules evaluated against the source, to skip or adapt the preset.
+ */
+rules?: Array<PresetRule>, 
+/**
+ * Names of the publish targets, which receive the output.
+ */
+publish_targets?: Array<string>, 
+/**
+ * Directory layer the preset was read from.
+ */
+source?: PresetSource | null, output_path?: string, finished: bool, };
+
+export type PresetRule = { if: RuleCondition, then: RuleAction, };
+
+/**
+ * Preset directories in ascending precedence, a preset in a later layer
+ * replaces a preset with the same name from an earlier one.
+ */
+export type PresetSource = /**
+ * Shipped with the application, read-only.
+ */
+"bundled" | /**
+ * Shared folder from the config, for example on a network drive.
+ */
+"shared" | /**
+ * Presets of the user, managed in the app.
+ */
+"user" | /**
+ * `.adconverter/presets` next to the source media.
+ */
+"project";
+
+/**
+ * Named set of values, which replace the base values while the profile is active.
+ */
+export type Profile = { copyright?: string | null, lufs?: LufsConfig | null, transcript_cmd?: string | null, download_path?: string | null, download_args?: string | null, default_presets?: Array<string> | null, };
 
-export type Publish = { name: string, thumbnail: string, description: string, tags: string, };
+export type Publish = { name: string, thumbnail: string, description: string, tags: string, 
+/**
+ * Endpoint, headers and metadata of tus targets for this task.
+ */
+tus?: TusPublish | null, };
 
-export type Task = { id: string, path: string, url: string | null, in: number, out: number, fade: boolean, lufs: boolean, transcript: string | null, probe: MediaProbe, presets: Array<Preset>, template: Template | null, target: string, target_subfolder: boolean, publish: Publish | null, active: bool, finished: bool, };
+/**
+ * Upload of one preset output to one target.
+ */
+export type PublishResult = { target: string, preset: string, status: PublishStatus, 
+/**
+ * Link to the published file or video.
+ */
+url: string | null, message: string | null, };
+
+export type PublishStatus = "published" | "failed" | "cancelled";
+
+/**
+ * Delivery target of `Config.publish_targets`, the `type` field selects the platform.
+ */
+export type PublishTarget = { "type": "peertube" } & PeerTubeConfig | { "type": "s3" } & S3Config | { "type": "tus" } & TusConfig | { "type": "webdav" } & WebDavConfig;
+
+/**
+ * What to do when the condition matches: skip the preset
+ * or override preset fields, merged like `extends`.
+ */
+export type RuleAction = RuleSkip | JsonValue;
+
+/**
+ * Source properties a rule matches on, all given fields must match.
+ */
+export type RuleCondition = { width_below?: bigint | null, width_above?: bigint | null, height_below?: bigint | null, height_above?: bigint | null, duration_below?: number | null, duration_above?: number | null, has_audio?: boolean | null, has_video?: boolean | null, orientation?: Orientation | null, };
+
+export type RuleSkip = "skip";
+
+/**
+ * Bucket of Amazon S3 or a compatible storage, like MinIO.
+ */
+export type S3Config = { 
+/**
+ * Like `https://s3.eu-central-1.amazonaws.com` or `http://localhost:9000`.
+ */
+endpoint: string, 
+/**
+ * `us-east-1` when empty.
+ */
+region: string, bucket: string, access_key: string, secret_key: string, 
+/**
+ * Bucket in the path instead of the host name, most compatible storages need it.
+ */
+path_style: boolean, 
+/**
+ * Object key with the placeholders `{name}`, `{source}`, `{preset}` and `{file}`,
+ * `{source}/{preset}/{file}` when empty.
+ */
+key: string, 
+/**
+ * `x-amz-meta-*` entries, the values can contain the placeholders of the key
+ * and `{description}` and `{tags}`.
+ */
+metadata: { [key in string]?: string }, 
+/**
+ * Size of the upload parts in MiB.
+ */
+part_size?: bigint | null, parallel_parts?: number | null, };
+
+/**
+ * Metadata of a downloaded file, read from the info JSON of yt-dlp.
+ */
+export type SourceMetadata = { title: string | null, description: string | null, uploader: string | null, 
+/**
+ * Upload date as `YYYYMMDD`.
+ */
+upload_date: string | null, tags: Array<string>, chapters: Array<Chapter>, 
+/**
+ * Local thumbnail image next to the file.
+ */
+thumbnail: string | null, webpage_url: string | null, };
+
+export type Task = { id: string, path: string, url: string | null, 
+/**
+ * Download all items of a playlist URL, also when it names a video.
+ */
+playlist: boolean, 
+/**
+ * Item range of a playlist URL, like `1-5,8`.
+ */
+playlist_items: string | null, 
+/**
+ * Expected hash of a direct download, like `sha256:<hex>`.
+ */
+checksum: string | null, in: number, out: number, fade: boolean, lufs: boolean, transcript: string | null, probe: MediaProbe, presets: Array<Preset>, template: Template | null, target: string, target_subfolder: boolean, publish: Publish | null, 
+/**
+ * Uploads of the last run.
+ */
+publish_results: Array<PublishResult>, 
+/**
+ * Metadata of a downloaded source, like title and chapters.
+ */
+metadata: SourceMetadata | null, 
+/**
+ * Encode all compatible presets in one ffmpeg process, sharing the decoder.
+ */
+shared_decode: boolean, 
+/**
+ * Config profile the task is encoded with, the active profile if not set.
+ */
+profile: string | null, active: bool, finished: bool, };
+
+/**
+ * Encoding of a task failed, the worker continues with the next task.
+ */
+export type TaskError = { id: string, message: string, };
 
 export type Template = { intro: string, intro_duration: number, outro: string, outro_duration: number, lower_thirds: Array<LowerThird>, };
 
+/**
+ * Server with the tus resumable upload protocol.
+ */
+export type TusConfig = { 
+/**
+ * Creation URL, like `https://example.org/files/`.
+ */
+endpoint: string, 
+/**
+ * Extra headers of every request, for example `Authorization`.
+ */
+headers: { [key in string]?: string }, 
+/**
+ * `Upload-Metadata` entries, the values can contain the placeholders
+ * `{name}`, `{description}`, `{tags}`, `{file}`, `{source}` and `{preset}`.
+ * Without entries the file name, type and title are sent.
+ */
+metadata: { [key in string]?: string }, 
+/**
+ * Size of the upload parts in MiB.
+ */
+chunk_size?: bigint | null, };
+
+/**
+ * Settings of tus targets in the publish fields of a task, they apply to all tus targets.
+ */
+export type TusPublish = { 
+/**
+ * Creation URL instead of the endpoint of the target, when not empty.
+ */
+endpoint: string, 
+/**
+ * Headers in addition to the headers of the target, the same names replace them.
+ */
+headers: { [key in string]?: string }, 
+/**
+ * `Upload-Metadata` entries in addition to the entries of the target,
+ * with the same placeholders.
+ */
+metadata: { [key in string]?: string }, };
+
+export type UploadProgress = { target: string, percent: number, };
+
 export type VideoStream = { codec_name: string | null, aspect_ratio: string | null, nb_frames: bigint | null, duration: number | null, bit_rate: bigint | null, width: bigint | null, height: bigint | null, };
+
+/**
+ * WebDAV folder, like a Nextcloud share.
+ */
+export type WebDavConfig = { 
+/**
+ * Base folder, for Nextcloud like `https://cloud.example.org/remote.php/dav/files/<user>/Deliveries`.
+ */
+url: string, username: string, 
+/**
+ * Password, for Nextcloud better an app password.
+ */
+password: string, 
+/**
+ * Folder below the base folder, can contain the placeholders `{name}`, `{source}` and `{preset}`.
+ * The subfolder of tasks with `target_subfolder` is added.
+ */
+folder: string, 
+/**
+ * Create a public Nextcloud share link of the uploaded file.
+ */
+share: boolean, };
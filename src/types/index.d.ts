@@ -1,4 +1,20 @@
-import type { FFmpegProgress, Preset, Task, Template, LufsConfig, Platform } from './backend'
+import type {
+    BackupEntry,
+    BackupPreview,
+    Config,
+    DownloadError,
+    DownloadProgress,
+    FFmpegProgress,
+    Preset,
+    Profile,
+    Task,
+    TaskError,
+    Template,
+    TusPublish,
+    LufsConfig,
+    Platform,
+    UploadProgress,
+} from './backend'
 
 export {}
 
@@ -10,6 +26,14 @@ declare global {
     type Config = Config
     type LufsConfig = LufsConfig
     type Platform = Platform
+    type BackupEntry = BackupEntry
+    type BackupPreview = BackupPreview
+    type Profile = Profile
+    type DownloadError = DownloadError
+    type DownloadProgress = DownloadProgress
+    type UploadProgress = UploadProgress
+    type TaskError = TaskError
+    type TusPublish = TusPublish
 
     type AlertObj = {
         text: string
@@ -1,7 +1,14 @@
 import assert from 'node:assert/strict'
 import test from 'node:test'
 
-import { findTaskById, finishedProgress, hasPendingTasks, nextPendingTask, taskHasWork } from '../src/composables/taskQueue.ts'
+import {
+    findTaskById,
+    finishedProgress,
+    hasPendingTasks,
+    nextPendingTask,
+    pendingDownloads,
+    taskHasWork,
+} from '../src/composables/taskQueue.ts'
 
 function task(overrides: Record<string, unknown> = {}) {
     return {
@@ -31,6 +38,13 @@ test('selects only pending and inactive queue entries', () => {
     assert.equal(hasPendingTasks([done]), false)
 })
 
+test('leaves url tasks to the download queue', () => {
+    const download = task({ url: 'https://example.test/video' })
+    const local = task()
+    assert.equal(nextPendingTask([download, local]), local)
+    assert.deepEqual(pendingDownloads([download, local, task({ url: 'https://example.test/done', finished: true })]), [download])
+})
+
 test('recognizes downloads and configured processing as work', () => {
     assert.equal(taskHasWork(task({ url: 'https://example.test/video' })), true)
     assert.equal(taskHasWork(task({ presets: [{}] })), true)